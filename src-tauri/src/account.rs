@@ -1,6 +1,6 @@
 use isideload::{
     developer_session::{DeveloperDeviceType, DeveloperSession, ListAppIdsResponse},
-    AnisetteConfiguration, AppleAccount,
+    AppleAccount,
 };
 use keyring::Entry;
 use once_cell::sync::OnceCell;
@@ -10,10 +10,16 @@ use std::{
     sync::{mpsc::RecvTimeoutError, Arc, Mutex},
     time::Duration,
 };
-use tauri::{AppHandle, Emitter, Listener, Manager, Window};
+use tauri::{AppHandle, Emitter, Listener, Window};
 use tauri_plugin_store::StoreExt;
+use tokio::sync::OwnedMutexGuard;
 use log::{error, warn, info, debug};
 
+use crate::{
+    anisette::{configuration, is_anisette_error, next_healthy, server_list},
+    operation::lock_resources,
+};
+
 pub static APPLE_ACCOUNT: OnceCell<Mutex<Option<Arc<AppleAccount>>>> = OnceCell::new();
 
 #[tauri::command]
@@ -25,10 +31,8 @@ pub async fn login_email_pass(
     anisette_server: String,
     save_credentials: bool,
 ) -> Result<String, String> {
-    let cell = APPLE_ACCOUNT.get_or_init(|| Mutex::new(None));
     let account = login(&handle, &window, email, password.clone(), anisette_server).await?;
-    let mut account_guard = cell.lock().unwrap();
-    *account_guard = Some(account.clone());
+    let _locks = set_account(account.clone()).await;
 
     if save_credentials {
         let pass_entry = Entry::new("iloader", &account.apple_id)
@@ -61,15 +65,13 @@ pub async fn login_stored_pass(
     email: String,
     anisette_server: String,
 ) -> Result<String, String> {
-    let cell = APPLE_ACCOUNT.get_or_init(|| Mutex::new(None));
     let pass_entry = Entry::new("iloader", &email)
         .map_err(|e| format!("Failed to create keyring entry for credentials: {:?}.", e))?;
     let password = pass_entry
         .get_password()
         .map_err(|e| format!("Failed to get credentials: {:?}", e))?;
     let account = login(&handle, &window, email, password, anisette_server).await?;
-    let mut account_guard = cell.lock().unwrap();
-    *account_guard = Some(account.clone());
+    set_account(account.clone()).await;
 
     Ok(account.apple_id.clone())
 }
@@ -104,12 +106,29 @@ pub fn logged_in_as() -> Option<String> {
     None
 }
 
+// Replaces the logged in account once no operation is using the one it replaces. Returns the
+// lock on the new account so the caller can finish setting it up first.
+async fn set_account(account: Arc<AppleAccount>) -> Vec<OwnedMutexGuard<()>> {
+    if let Some(old) = logged_in_as() {
+        if !old.eq_ignore_ascii_case(&account.apple_id) {
+            let _old_locks = lock_resources(None, Some(&old)).await;
+            *APPLE_ACCOUNT.get_or_init(|| Mutex::new(None)).lock().unwrap() = None;
+        }
+    }
+    let locks = lock_resources(None, Some(&account.apple_id)).await;
+    *APPLE_ACCOUNT.get_or_init(|| Mutex::new(None)).lock().unwrap() = Some(account);
+    locks
+}
+
 #[tauri::command]
-pub fn invalidate_account() {
-    let cell = APPLE_ACCOUNT.get();
-    if let Some(account) = cell {
-        let mut account_guard = account.lock().unwrap();
-        *account_guard = None;
+pub async fn invalidate_account() {
+    let Some(apple_id) = logged_in_as() else {
+        return;
+    };
+    let _locks = lock_resources(None, Some(&apple_id)).await;
+    let mut current = APPLE_ACCOUNT.get_or_init(|| Mutex::new(None)).lock().unwrap();
+    if current.as_ref().is_some_and(|a| a.apple_id == apple_id) {
+        *current = None;
     }
 }
 
@@ -131,8 +150,13 @@ pub async fn get_developer_session() -> Result<DeveloperSession, String> {
         error!("No account available: {}", e);
         e
     })?;
+    developer_session(account).await
+}
 
-    let mut dev_session = DeveloperSession::new(account);
+// A session for `account`, which operations get before waiting for its lock so a login while
+// they wait can't switch them to another account
+pub async fn developer_session(account: Arc<AppleAccount>) -> Result<DeveloperSession, String> {
+    let mut dev_session = DeveloperSession::new(account.clone());
 
     let teams = match dev_session.list_teams().await {
         Ok(t) => {
@@ -148,7 +172,12 @@ pub async fn get_developer_session() -> Result<DeveloperSession, String> {
             };
             if is_22411 {
                 warn!("Session expired (error -22411), invalidating account");
-                invalidate_account();
+                // The caller may already hold the account's lock, so this doesn't take it
+                let cell = APPLE_ACCOUNT.get_or_init(|| Mutex::new(None));
+                let mut current = cell.lock().unwrap();
+                if current.as_ref().is_some_and(|a| Arc::ptr_eq(a, &account)) {
+                    *current = None;
+                }
                 return Err(format!("Session timed out, please try again: {:?}", e));
             } else {
                 error!("Failed to list teams: {:?}", e);
@@ -168,21 +197,15 @@ pub async fn get_developer_session() -> Result<DeveloperSession, String> {
     Ok(dev_session)
 }
 
-async fn login(
-    handle: &AppHandle,
-    window: &Window,
-    email: String,
-    password: String,
-    anisette_server: String,
-) -> Result<Arc<AppleAccount>, String> {
-    let (tx, rx) = std::sync::mpsc::channel::<String>();
+// Asks the frontend for the 2FA code and waits up to two minutes for it
+fn two_factor_prompt(window: &Window) -> impl Fn() -> Result<String, String> {
     let window_clone = window.clone();
-    let tfa_closure = move || -> Result<String, String> {
+    move || -> Result<String, String> {
+        let (tx, rx) = std::sync::mpsc::channel::<String>();
         window_clone
             .emit("2fa-required", ())
             .expect("Failed to emit 2fa-required event");
 
-        let tx = tx.clone();
         let handler_id = window_clone.listen("2fa-recieved", move |event| {
             let code = event.payload();
             let _ = tx.send(code.to_string());
@@ -199,25 +222,44 @@ async fn login(
             Err(RecvTimeoutError::Timeout) => Err("2FA cancelled or timed out".to_string()),
             Err(RecvTimeoutError::Disconnected) => Err("2FA disconnected".to_string()),
         }
-    };
+    }
+}
 
-    let config = AnisetteConfiguration::default();
-    let config =
-        config.set_configuration_path(handle.path().app_config_dir().map_err(|e| e.to_string())?);
-    let config = config.set_anisette_url_v3(format!("https://{}", anisette_server));
-
-    let account = AppleAccount::login(
-        || Ok((email.clone().to_lowercase(), password.clone())),
-        tfa_closure,
-        config,
-    )
-    .await;
-    if let Err(e) = account {
-        return Err(e.to_string());
+// Logs in through the first anisette server that is up, starting with the selected one, and
+// moves on to the next if the server fails during login
+async fn login(
+    handle: &AppHandle,
+    window: &Window,
+    email: String,
+    password: String,
+    anisette_server: String,
+) -> Result<Arc<AppleAccount>, String> {
+    let mut servers = server_list(handle, Some(&anisette_server))?;
+    let mut last_error = None;
+    while let Some(health) = next_healthy(&mut servers).await {
+        let config = configuration(handle, &health)?;
+        let account = AppleAccount::login(
+            || Ok((email.clone().to_lowercase(), password.clone())),
+            two_factor_prompt(window),
+            config,
+        )
+        .await;
+        match account {
+            Ok(account) => return Ok(Arc::new(account)),
+            Err(e) if is_anisette_error(&e) => {
+                warn!("Login through {} failed: {}", health.url, e);
+                last_error = Some(e.to_string());
+            }
+            Err(e) => return Err(e.to_string()),
+        }
     }
-    let account = Arc::new(account.unwrap());
 
-    Ok(account)
+    Err(match last_error {
+        Some(e) => format!("Login failed on every anisette server: {}", e),
+        None => "No anisette server is reachable. Check your connection or add another server in \
+                 Settings."
+            .to_string(),
+    })
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -388,7 +430,9 @@ pub async fn get_certificates() -> Result<Vec<CertificateInfo>, String> {
 
 #[tauri::command]
 pub async fn revoke_certificate(serial_number: String) -> Result<(), String> {
-    let dev_session = get_developer_session().await?;
+    let account = get_account()?;
+    let _locks = lock_resources(None, Some(&account.apple_id)).await;
+    let dev_session = developer_session(account).await?;
     let team = dev_session
         .get_team()
         .await
@@ -416,7 +460,9 @@ pub async fn list_app_ids() -> Result<ListAppIdsResponse, String> {
 
 #[tauri::command]
 pub async fn delete_app_id(app_id_id: String) -> Result<(), String> {
-    let dev_session = get_developer_session().await?;
+    let account = get_account()?;
+    let _locks = lock_resources(None, Some(&account.apple_id)).await;
+    let dev_session = developer_session(account).await?;
     let team = dev_session
         .get_team()
         .await
@@ -438,7 +484,9 @@ pub struct CleanupResult {
 
 #[tauri::command]
 pub async fn cleanup_all() -> Result<CleanupResult, String> {
-    let dev_session = get_developer_session().await?;
+    let account = get_account()?;
+    let _locks = lock_resources(None, Some(&account.apple_id)).await;
+    let dev_session = developer_session(account).await?;
     let team = dev_session
         .get_team()
         .await
@@ -0,0 +1,269 @@
+use std::time::{Duration, Instant};
+
+use futures::future::join_all;
+use isideload::AnisetteConfiguration;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Manager};
+use tauri_plugin_store::StoreExt;
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Listed on the settings page after the user's own, and tried in this order after them
+const BUILTIN_SERVERS: [(&str, &str); 6] = [
+    ("https://ani.sidestore.io", "SideStore (.io)"),
+    ("https://ani.sidestore.app", "SideStore (.app)"),
+    ("https://ani.sidestore.zip", "SideStore (.zip)"),
+    ("https://ani.846969.xyz", "SideStore (.xyz)"),
+    ("https://anisette.seasi.dev", "SeasiDev"),
+    ("https://ani.xu30.top", "SteX"),
+];
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnisetteServer {
+    pub url: String,
+    pub name: String,
+    #[serde(default)]
+    pub builtin: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnisetteProtocol {
+    V1,
+    V3,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnisetteHealth {
+    pub url: String,
+    pub name: String,
+    pub reachable: bool,
+    pub latency_ms: Option<u64>,
+    pub protocol: Option<AnisetteProtocol>,
+    pub error: Option<String>,
+}
+
+// Servers used to be stored as a bare host and always reached over HTTPS. Local servers can be
+// given with an explicit http:// instead.
+pub fn normalize_url(server: &str) -> String {
+    let server = server.trim().trim_end_matches('/');
+    if server.starts_with("http://") || server.starts_with("https://") {
+        server.to_string()
+    } else {
+        format!("https://{}", server)
+    }
+}
+
+fn load_custom_servers(handle: &AppHandle) -> Result<Vec<AnisetteServer>, String> {
+    let store = handle
+        .store("anisette.json")
+        .map_err(|e| format!("Failed to get store: {:?}", e))?;
+    Ok(store
+        .get("servers")
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default())
+}
+
+fn save_custom_servers(handle: &AppHandle, servers: &[AnisetteServer]) -> Result<(), String> {
+    let store = handle
+        .store("anisette.json")
+        .map_err(|e| format!("Failed to get store: {:?}", e))?;
+    store.set(
+        "servers",
+        serde_json::to_value(servers).map_err(|e| e.to_string())?,
+    );
+    Ok(())
+}
+
+// The selected server first, then the ones the user added, then the built-in ones
+pub fn server_list(
+    handle: &AppHandle,
+    preferred: Option<&str>,
+) -> Result<Vec<AnisetteServer>, String> {
+    let builtin = BUILTIN_SERVERS.iter().map(|(url, name)| AnisetteServer {
+        url: url.to_string(),
+        name: name.to_string(),
+        builtin: true,
+    });
+    let mut servers: Vec<AnisetteServer> = vec![];
+    let mut add = |server: AnisetteServer| {
+        if !servers.iter().any(|s| s.url == server.url) {
+            servers.push(server);
+        }
+    };
+    let custom = load_custom_servers(handle)?;
+    if let Some(preferred) = preferred.filter(|p| !p.trim().is_empty()) {
+        let url = normalize_url(preferred);
+        let known = custom
+            .iter()
+            .cloned()
+            .chain(builtin.clone())
+            .find(|s| s.url == url);
+        add(known.unwrap_or(AnisetteServer {
+            name: url.clone(),
+            url,
+            builtin: false,
+        }));
+    }
+    custom.into_iter().for_each(&mut add);
+    builtin.for_each(&mut add);
+    Ok(servers)
+}
+
+fn probe_client() -> Result<reqwest::Client, String> {
+    reqwest::Client::builder()
+        .timeout(PROBE_TIMEOUT)
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))
+}
+
+async fn get_json(client: &reqwest::Client, url: &str) -> Result<Value, String> {
+    let response = client.get(url).send().await.map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("HTTP {}", response.status()));
+    }
+    response.json().await.map_err(|e| e.to_string())
+}
+
+// v3 servers describe the client they emulate at /v3/client_info, v1 servers hand out anisette
+// headers directly at their root
+pub async fn probe(server: &AnisetteServer) -> AnisetteHealth {
+    let mut health = AnisetteHealth {
+        url: server.url.clone(),
+        name: server.name.clone(),
+        reachable: false,
+        latency_ms: None,
+        protocol: None,
+        error: None,
+    };
+    let client = match probe_client() {
+        Ok(client) => client,
+        Err(e) => {
+            health.error = Some(e);
+            return health;
+        }
+    };
+
+    let started = Instant::now();
+    let v3 = get_json(&client, &format!("{}/v3/client_info", server.url)).await;
+    health.latency_ms = Some(started.elapsed().as_millis() as u64);
+    match v3 {
+        Ok(info) if info.get("client_info").is_some() => {
+            health.reachable = true;
+            health.protocol = Some(AnisetteProtocol::V3);
+            return health;
+        }
+        Ok(_) => {}
+        Err(e) => debug!("{} has no v3 endpoint: {}", server.url, e),
+    }
+
+    let started = Instant::now();
+    match get_json(&client, &server.url).await {
+        Ok(headers) if headers.get("X-Apple-I-MD").is_some() => {
+            health.latency_ms = Some(started.elapsed().as_millis() as u64);
+            health.reachable = true;
+            health.protocol = Some(AnisetteProtocol::V1);
+        }
+        Ok(_) => health.error = Some("Not an anisette server".to_string()),
+        Err(e) => {
+            health.latency_ms = None;
+            health.error = Some(e);
+        }
+    }
+    health
+}
+
+// Anisette configuration for a server that passed its probe
+pub fn configuration(
+    handle: &AppHandle,
+    health: &AnisetteHealth,
+) -> Result<AnisetteConfiguration, String> {
+    let config = AnisetteConfiguration::default()
+        .set_configuration_path(handle.path().app_config_dir().map_err(|e| e.to_string())?);
+    Ok(match health.protocol {
+        Some(AnisetteProtocol::V1) => config.set_anisette_url(health.url.clone()),
+        _ => config.set_anisette_url_v3(health.url.clone()),
+    })
+}
+
+// Errors that mean the anisette server, rather than the Apple ID, is the problem
+pub fn is_anisette_error(error: &icloud_auth::Error) -> bool {
+    matches!(error, icloud_auth::Error::ErrorGettingAnisette(_))
+}
+
+// Probes the servers in order until one works, for login to try them one at a time
+pub async fn next_healthy(servers: &mut Vec<AnisetteServer>) -> Option<AnisetteHealth> {
+    while !servers.is_empty() {
+        let server = servers.remove(0);
+        let health = probe(&server).await;
+        if health.reachable {
+            info!(
+                "Using anisette server {} ({:?}, {} ms)",
+                health.url,
+                health.protocol,
+                health.latency_ms.unwrap_or_default()
+            );
+            return Some(health);
+        }
+        warn!(
+            "Anisette server {} is unavailable: {}",
+            server.url,
+            health.error.as_deref().unwrap_or("unknown error")
+        );
+    }
+    None
+}
+
+#[tauri::command]
+pub fn list_anisette_servers(
+    handle: AppHandle,
+    anisette_server: Option<String>,
+) -> Result<Vec<AnisetteServer>, String> {
+    server_list(&handle, anisette_server.as_deref())
+}
+
+#[tauri::command]
+pub fn add_anisette_server(
+    handle: AppHandle,
+    url: String,
+    name: Option<String>,
+) -> Result<AnisetteServer, String> {
+    if url.trim().is_empty() {
+        return Err("The server URL is empty".to_string());
+    }
+    let url = normalize_url(&url);
+    let server = AnisetteServer {
+        name: name
+            .filter(|n| !n.trim().is_empty())
+            .unwrap_or_else(|| url.clone()),
+        url,
+        builtin: false,
+    };
+    let mut servers = load_custom_servers(&handle)?;
+    servers.retain(|s| s.url != server.url);
+    servers.push(server.clone());
+    save_custom_servers(&handle, &servers)?;
+    Ok(server)
+}
+
+#[tauri::command]
+pub fn remove_anisette_server(handle: AppHandle, url: String) -> Result<(), String> {
+    let url = normalize_url(&url);
+    let mut servers = load_custom_servers(&handle)?;
+    servers.retain(|s| s.url != url);
+    save_custom_servers(&handle, &servers)
+}
+
+// Probes every server at once, in the order login would try them
+#[tauri::command]
+pub async fn check_anisette_servers(
+    handle: AppHandle,
+    anisette_server: Option<String>,
+) -> Result<Vec<AnisetteHealth>, String> {
+    let servers = server_list(&handle, anisette_server.as_deref())?;
+    Ok(join_all(servers.iter().map(probe)).await)
+}
@@ -0,0 +1,270 @@
+use idevice::{
+    installation_proxy::InstallationProxyClient, misagent::MisagentClient, IdeviceService,
+};
+use log::{debug, info, warn};
+use serde::Serialize;
+use serde_json::json;
+use tauri::{AppHandle, State, Window};
+use tokio::sync::mpsc::unbounded_channel;
+
+use crate::{
+    account::{get_account, get_certificates, get_developer_session},
+    device::{get_provider, DeviceInfo, DeviceInfoMutex},
+    inventory::{load_inventory, remove_install},
+    ipa::{parse_provisioning_profile, plist_string, ProfileInfo},
+    operation::Operation,
+};
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserAppInfo {
+    pub bundle_id: String,
+    pub name: String,
+    pub version: Option<String>,
+    pub build: Option<String>,
+    pub signer_identity: Option<String>,
+    pub profile_name: Option<String>,
+    pub profile_expiry: Option<u64>,
+    pub team_id: Option<String>,
+    pub team_name: Option<String>,
+    pub certificate_serial: Option<String>,
+    // Set when the app's team is the logged in one and the certificate that signed it is no
+    // longer listed, meaning it was revoked and the app won't launch anymore. None when it
+    // couldn't be checked.
+    pub certificate_revoked: Option<bool>,
+    pub in_inventory: bool,
+}
+
+fn selected_device(device_state: &State<'_, DeviceInfoMutex>) -> Result<DeviceInfo, String> {
+    let device_lock = device_state.lock().unwrap();
+    match &*device_lock {
+        Some(d) => Ok(d.clone()),
+        None => Err("No device selected".to_string()),
+    }
+}
+
+async fn installation_proxy(device: &DeviceInfo) -> Result<InstallationProxyClient, String> {
+    let provider = get_provider(device).await?;
+    InstallationProxyClient::connect(&provider)
+        .await
+        .map_err(|e| format!("Failed to connect to installation proxy: {}", e))
+}
+
+// All provisioning profiles installed on the device
+async fn installed_profiles(device: &DeviceInfo) -> Result<Vec<ProfileInfo>, String> {
+    let provider = get_provider(device).await?;
+    let mut misagent = MisagentClient::connect(&provider)
+        .await
+        .map_err(|e| format!("Failed to connect to misagent: {}", e))?;
+    let profiles = misagent
+        .copy_all()
+        .await
+        .map_err(|e| format!("Failed to get provisioning profiles: {}", e))?;
+    Ok(profiles
+        .iter()
+        .filter_map(|p| parse_provisioning_profile(p))
+        .collect())
+}
+
+fn profile_matches(profile: &ProfileInfo, bundle_id: &str) -> bool {
+    let Some(app_id) = profile
+        .application_identifier
+        .as_deref()
+        .and_then(|id| id.split_once('.'))
+        .map(|(_, id)| id)
+    else {
+        return false;
+    };
+    match app_id.strip_suffix('*') {
+        Some(prefix) => bundle_id.starts_with(prefix),
+        None => app_id == bundle_id,
+    }
+}
+
+// Picks the profile that's valid the longest, since a refresh leaves older profiles behind. An
+// exact App ID wins over a wildcard.
+fn profile_for<'a>(profiles: &'a [ProfileInfo], bundle_id: &str) -> Option<&'a ProfileInfo> {
+    profiles
+        .iter()
+        .filter(|p| profile_matches(p, bundle_id))
+        .max_by_key(|p| {
+            let wildcard = p
+                .application_identifier
+                .as_deref()
+                .is_some_and(|id| id.ends_with('*'));
+            (!wildcard, p.expiration)
+        })
+}
+
+pub fn normalize_serial(serial: &str) -> String {
+    serial.trim_start_matches('0').to_uppercase()
+}
+
+// Serials of the logged in account's certificates and its team ID, if logged in
+async fn current_certificates() -> Option<(String, Vec<String>)> {
+    get_account().ok()?;
+    let dev_session = get_developer_session().await.ok()?;
+    let team = dev_session.get_team().await.ok()?;
+    match get_certificates().await {
+        Ok(certs) => Some((
+            team.team_id,
+            certs
+                .iter()
+                .map(|c| normalize_serial(&c.serial_number))
+                .collect(),
+        )),
+        Err(e) => {
+            warn!(
+                "Failed to get certificates, skipping revocation check: {}",
+                e
+            );
+            None
+        }
+    }
+}
+
+pub async fn user_apps(
+    handle: &AppHandle,
+    device: &DeviceInfo,
+) -> Result<Vec<UserAppInfo>, String> {
+    let mut installation_proxy = installation_proxy(device).await?;
+    let installed_apps = installation_proxy
+        .get_apps(Some("User"), None)
+        .await
+        .map_err(|e| format!("Failed to get installed apps: {}", e))?;
+    let inventory = load_inventory(handle)?;
+    let profiles = installed_profiles(device).await.unwrap_or_else(|e| {
+        warn!("{}", e);
+        vec![]
+    });
+    let certificates = current_certificates().await;
+
+    let mut apps: Vec<UserAppInfo> = installed_apps
+        .into_iter()
+        .map(|(bundle_id, info)| {
+            let info = info.as_dictionary().cloned().unwrap_or_default();
+            let entry = inventory
+                .iter()
+                .find(|e| e.udid == device.uuid && e.bundle_id == bundle_id);
+            let profile = profile_for(&profiles, &bundle_id);
+            if profile.is_none() {
+                debug!("No provisioning profile found for {}", bundle_id);
+            }
+            let team_id = profile
+                .and_then(|p| p.team_id.clone())
+                .or_else(|| entry.map(|e| e.team_id.clone()));
+            // The signer is only known for apps iloader installed. Otherwise a profile with a
+            // single certificate leaves no doubt about which one signed the app.
+            let certificate_serial = entry
+                .and_then(|e| e.certificate_serial.clone())
+                .or_else(|| match profile.map(|p| p.certificate_serials.as_slice()) {
+                    Some([serial]) => Some(serial.clone()),
+                    _ => None,
+                });
+            let certificate_revoked = match (&certificates, profile) {
+                (Some((current_team, serials)), Some(profile))
+                    if team_id.as_ref() == Some(current_team) =>
+                {
+                    match &certificate_serial {
+                        Some(serial) => Some(!serials.contains(&normalize_serial(serial))),
+                        // Without the signer only a profile whose certificates are all gone is
+                        // known to be revoked
+                        None if !profile
+                            .certificate_serials
+                            .iter()
+                            .any(|s| serials.contains(&normalize_serial(s))) =>
+                        {
+                            Some(true)
+                        }
+                        None => None,
+                    }
+                }
+                _ => None,
+            };
+            UserAppInfo {
+                name: plist_string(&info, "CFBundleDisplayName")
+                    .or_else(|| plist_string(&info, "CFBundleName"))
+                    .unwrap_or_else(|| bundle_id.clone()),
+                version: plist_string(&info, "CFBundleShortVersionString"),
+                build: plist_string(&info, "CFBundleVersion"),
+                signer_identity: plist_string(&info, "SignerIdentity"),
+                profile_name: profile.and_then(|p| p.name.clone()),
+                profile_expiry: profile
+                    .and_then(|p| p.expiration)
+                    .or_else(|| entry.and_then(|e| e.profile_expiry)),
+                team_name: profile
+                    .and_then(|p| p.team_name.clone())
+                    .or_else(|| entry.and_then(|e| e.team_name.clone())),
+                team_id,
+                certificate_serial,
+                certificate_revoked,
+                in_inventory: entry.is_some(),
+                bundle_id,
+            }
+        })
+        .collect();
+    apps.sort_by_key(|a| a.name.to_lowercase());
+    Ok(apps)
+}
+
+pub async fn uninstall(
+    handle: &AppHandle,
+    device: &DeviceInfo,
+    bundle_id: &str,
+    on_progress: impl Fn(u64) + Clone,
+) -> Result<(), String> {
+    info!("Uninstalling {} from {}", bundle_id, device.name);
+    let mut installation_proxy = installation_proxy(device).await?;
+    installation_proxy
+        .uninstall_with_callback(
+            bundle_id,
+            None,
+            |(percent, on_progress)| async move { on_progress(percent) },
+            on_progress,
+        )
+        .await
+        .map_err(|e| format!("Failed to uninstall {}: {}", bundle_id, e))?;
+
+    if let Err(e) = remove_install(handle, &device.uuid, bundle_id) {
+        warn!("Failed to remove {} from inventory: {}", bundle_id, e);
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn list_user_apps(
+    handle: AppHandle,
+    device_state: State<'_, DeviceInfoMutex>,
+) -> Result<Vec<UserAppInfo>, String> {
+    let device = selected_device(&device_state)?;
+    user_apps(&handle, &device).await
+}
+
+#[tauri::command]
+pub async fn uninstall_app_operation(
+    handle: AppHandle,
+    window: Window,
+    run_id: Option<u64>,
+    device_state: State<'_, DeviceInfoMutex>,
+    bundle_id: String,
+) -> Result<(), String> {
+    let op = Operation::new("uninstall_app".to_string(), &window, run_id);
+    op.set_params(json!({ "bundleId": bundle_id }));
+    op.start("uninstall")?;
+    let device = op.fail_if_err("uninstall", selected_device(&device_state))?;
+    op.wait_for("uninstall", Some(&device.uuid), None).await?;
+    let (tx, rx) = unbounded_channel();
+    op.fail_if_err(
+        "uninstall",
+        op.track(
+            "uninstall",
+            rx,
+            uninstall(&handle, &device, &bundle_id, move |percent| {
+                let _ = tx.send(percent);
+            }),
+        )
+        .await,
+    )?;
+    op.complete("uninstall")?;
+    Ok(())
+}
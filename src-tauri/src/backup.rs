@@ -0,0 +1,328 @@
+use std::{
+    cmp::Reverse,
+    fs::File,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use idevice::{afc::AfcClient, installation_proxy::InstallationProxyClient, IdeviceService};
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::json;
+use tauri::{AppHandle, Manager, State, Window};
+use zip::{write::SimpleFileOptions, ZipArchive, ZipWriter};
+
+use crate::{
+    device::{get_provider, DeviceInfo, DeviceInfoMutex},
+    files::{download, open_container, upload, walk_local},
+    operation::Operation,
+};
+
+// The rest of the container (tmp, the container manager metadata) is either recreated by iOS or
+// not writable over house_arrest, so only these are archived.
+const BACKUP_ROOTS: &[&str] = &["Documents", "Library"];
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppBackupInfo {
+    pub bundle_id: String,
+    pub path: String,
+    pub created: u64,
+    pub size: u64,
+}
+
+fn backups_dir(handle: &AppHandle) -> Result<PathBuf, String> {
+    let dir = handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data dir: {:?}", e))?
+        .join("backups");
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create backups dir: {}", e))?;
+    Ok(dir)
+}
+
+// Keeps staging dirs of runs on the same app, e.g. a backup before an install and a manual one,
+// apart
+fn unique_suffix() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    format!(
+        "{}-{}",
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos(),
+        COUNTER.fetch_add(1, Ordering::SeqCst)
+    )
+}
+
+fn staging_dir(handle: &AppHandle, name: &str) -> Result<PathBuf, String> {
+    let dir = handle
+        .path()
+        .temp_dir()
+        .map_err(|e| format!("Failed to get temp dir: {:?}", e))?
+        .join(format!("iloader-{}-{}", name, unique_suffix()));
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create staging dir: {}", e))?;
+    Ok(dir)
+}
+
+// Backups are named `<bundle ID>_<created>.zip`, with a counter after the time when several are
+// made in the same second. The file is created here so two backups can't claim the same name.
+fn create_backup_file(
+    dir: &Path,
+    bundle_id: &str,
+    created: u64,
+) -> Result<(PathBuf, File), String> {
+    let mut n = 0;
+    loop {
+        let name = match n {
+            0 => format!("{}_{}.zip", bundle_id, created),
+            n => format!("{}_{}-{}.zip", bundle_id, created, n),
+        };
+        let path = dir.join(name);
+        match File::create_new(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => n += 1,
+            Err(e) => return Err(format!("Failed to create backup: {}", e)),
+        }
+    }
+}
+
+fn zip_dir(src: &Path, file: File) -> Result<(), String> {
+    let (dirs, files) = walk_local(src)?;
+    let mut zip = ZipWriter::new(file);
+    let options = SimpleFileOptions::default();
+
+    for dir in dirs {
+        zip.add_directory(dir, options)
+            .map_err(|e| format!("Failed to write backup: {}", e))?;
+    }
+    for (local, rel) in files {
+        let contents =
+            std::fs::read(&local).map_err(|e| format!("Failed to read {:?}: {}", local, e))?;
+        zip.start_file(rel, options)
+            .map_err(|e| format!("Failed to write backup: {}", e))?;
+        zip.write_all(&contents)
+            .map_err(|e| format!("Failed to write backup: {}", e))?;
+    }
+
+    zip.finish()
+        .map_err(|e| format!("Failed to finish backup: {}", e))?;
+    Ok(())
+}
+
+// Downloads every backup root the container has. A missing root is skipped, any other error
+// fails the backup so it is never silently incomplete.
+async fn download_roots(
+    afc_client: &mut AfcClient,
+    bundle_id: &str,
+    staging: &Path,
+) -> Result<(), String> {
+    let present = afc_client
+        .list_dir("/")
+        .await
+        .map_err(|e| format!("Failed to list the app container: {}", e))?;
+    for root in BACKUP_ROOTS {
+        if !present.iter().any(|p| p == root) {
+            warn!("{} has no {} to back up", bundle_id, root);
+            continue;
+        }
+        download(
+            afc_client,
+            &format!("/{}", root),
+            &staging.join(root),
+            |_, _, _, _, _| {},
+        )
+        .await
+        .map_err(|e| format!("Failed to back up {}: {}", root, e))?;
+    }
+    Ok(())
+}
+
+pub async fn backup_app_data(
+    handle: &AppHandle,
+    device: &DeviceInfo,
+    bundle_id: &str,
+) -> Result<PathBuf, String> {
+    info!("Backing up app data for {}", bundle_id);
+    let mut afc_client = open_container(device, bundle_id.to_string(), true).await?;
+    let staging = staging_dir(handle, &format!("backup-{}", bundle_id))?;
+
+    let res = download_roots(&mut afc_client, bundle_id, &staging).await;
+    if let Err(e) = res {
+        let _ = std::fs::remove_dir_all(&staging);
+        return Err(e);
+    }
+
+    let created = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let res = backups_dir(handle)
+        .and_then(|dir| create_backup_file(&dir, bundle_id, created))
+        .and_then(|(dest, file)| {
+            let res = zip_dir(&staging, file);
+            if res.is_err() {
+                let _ = std::fs::remove_file(&dest);
+            }
+            res.map(|_| dest)
+        });
+    let _ = std::fs::remove_dir_all(&staging);
+    let dest = res?;
+
+    info!("Backed up {} to {:?}", bundle_id, dest);
+    Ok(dest)
+}
+
+pub async fn restore_app_data(
+    handle: &AppHandle,
+    device: &DeviceInfo,
+    bundle_id: &str,
+    backup_path: &Path,
+) -> Result<(), String> {
+    info!("Restoring {:?} into {}", backup_path, bundle_id);
+    let staging = staging_dir(handle, &format!("restore-{}", bundle_id))?;
+    let file = File::open(backup_path).map_err(|e| format!("Failed to open backup: {}", e))?;
+    ZipArchive::new(file)
+        .and_then(|mut archive| archive.extract(&staging))
+        .map_err(|e| format!("Failed to extract backup: {}", e))?;
+
+    let mut afc_client = open_container(device, bundle_id.to_string(), true).await?;
+    let mut res = Ok(());
+    for root in BACKUP_ROOTS {
+        let local = staging.join(root);
+        if !local.exists() {
+            continue;
+        }
+        res = upload(
+            &mut afc_client,
+            &local,
+            &format!("/{}", root),
+            |_, _, _, _, _| {},
+        )
+        .await;
+        if res.is_err() {
+            break;
+        }
+    }
+
+    let _ = std::fs::remove_dir_all(&staging);
+    res
+}
+
+// Backs up the first of `bundle_ids` that is installed on the device, if any.
+pub async fn backup_if_installed(
+    handle: &AppHandle,
+    device: &DeviceInfo,
+    bundle_ids: &[String],
+) -> Result<Option<PathBuf>, String> {
+    let provider = get_provider(device).await?;
+    let mut installation_proxy = InstallationProxyClient::connect(&provider)
+        .await
+        .map_err(|e| format!("Failed to connect to installation proxy: {}", e))?;
+
+    let installed_apps = installation_proxy
+        .get_apps(Some("User"), None)
+        .await
+        .map_err(|e| format!("Failed to get installed apps: {}", e))?;
+
+    match bundle_ids
+        .iter()
+        .find(|id| installed_apps.contains_key(*id))
+    {
+        Some(bundle_id) => Ok(Some(backup_app_data(handle, device, bundle_id).await?)),
+        None => Ok(None),
+    }
+}
+
+#[tauri::command]
+pub fn list_app_backups(
+    handle: AppHandle,
+    bundle_id: Option<String>,
+) -> Result<Vec<AppBackupInfo>, String> {
+    let dir = backups_dir(&handle)?;
+    let read_dir = std::fs::read_dir(&dir).map_err(|e| format!("Failed to read backups: {}", e))?;
+
+    let mut backups = Vec::new();
+    for entry in read_dir.flatten() {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        let Some((id, created)) = file_name
+            .strip_suffix(".zip")
+            .and_then(|n| n.rsplit_once('_'))
+        else {
+            continue;
+        };
+        if bundle_id.as_ref().is_some_and(|b| b != id) {
+            continue;
+        }
+        backups.push(AppBackupInfo {
+            bundle_id: id.to_string(),
+            path: entry.path().to_string_lossy().to_string(),
+            created: created
+                .split('-')
+                .next()
+                .and_then(|c| c.parse().ok())
+                .unwrap_or(0),
+            size: entry.metadata().map(|m| m.len()).unwrap_or(0),
+        });
+    }
+
+    backups.sort_by_key(|b| Reverse(b.created));
+    Ok(backups)
+}
+
+#[tauri::command]
+pub async fn backup_app_data_operation(
+    handle: AppHandle,
+    window: Window,
+    run_id: Option<u64>,
+    device_state: State<'_, DeviceInfoMutex>,
+    bundle_id: String,
+) -> Result<String, String> {
+    let op = Operation::new("backup_app_data".to_string(), &window, run_id);
+    op.set_params(json!({ "bundleId": bundle_id }));
+    op.start("backup")?;
+    let device = {
+        let device_guard = device_state.lock().unwrap();
+        match &*device_guard {
+            Some(d) => d.clone(),
+            None => return op.fail("backup", "No device selected".to_string()),
+        }
+    };
+    op.wait_for("backup", Some(&device.uuid), None).await?;
+    let path = op.fail_if_err(
+        "backup",
+        backup_app_data(&handle, &device, &bundle_id).await,
+    )?;
+    op.complete("backup")?;
+    Ok(path.to_string_lossy().to_string())
+}
+
+#[tauri::command]
+pub async fn restore_app_data_operation(
+    handle: AppHandle,
+    window: Window,
+    run_id: Option<u64>,
+    device_state: State<'_, DeviceInfoMutex>,
+    bundle_id: String,
+    backup_path: String,
+) -> Result<(), String> {
+    let op = Operation::new("restore_app_data".to_string(), &window, run_id);
+    op.set_params(json!({ "bundleId": bundle_id, "backupPath": backup_path }));
+    op.start("restore")?;
+    let device = {
+        let device_guard = device_state.lock().unwrap();
+        match &*device_guard {
+            Some(d) => d.clone(),
+            None => return op.fail("restore", "No device selected".to_string()),
+        }
+    };
+    op.wait_for("restore", Some(&device.uuid), None).await?;
+    op.fail_if_err(
+        "restore",
+        restore_app_data(&handle, &device, &bundle_id, Path::new(&backup_path)).await,
+    )?;
+    op.complete("restore")?;
+    Ok(())
+}
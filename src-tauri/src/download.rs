@@ -0,0 +1,305 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime},
+};
+
+use log::{error, info, warn};
+use reqwest::{header, StatusCode};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, Window};
+use tauri_plugin_store::StoreExt;
+
+use crate::operation::{CancelToken, CANCELLED};
+
+// Nothing iloader installs comes close, this only stops a bad link from filling the disk
+pub const MAX_DOWNLOAD_BYTES: u64 = 4 * 1024 * 1024 * 1024;
+// Cached downloads unused for this long are deleted, then the least recently used ones until the
+// cache fits in MAX_CACHE_BYTES
+const MAX_CACHE_AGE: Duration = Duration::from_secs(14 * 24 * 60 * 60);
+const MAX_CACHE_BYTES: u64 = 2 * 1024 * 1024 * 1024;
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DownloadProgress<'a> {
+    url: &'a str,
+    bytes_done: u64,
+    bytes_total: Option<u64>,
+}
+
+// Validators of a cached download, used to skip downloading it again if it hasn't changed
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CachedDownload {
+    file: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+pub fn is_url(source: &str) -> bool {
+    source.starts_with("https://") || source.starts_with("http://")
+}
+
+fn cache_dir(handle: &AppHandle) -> Result<PathBuf, String> {
+    let dir = handle
+        .path()
+        .app_cache_dir()
+        .map_err(|e| format!("Failed to get cache dir: {:?}", e))?
+        .join("downloads");
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create cache dir: {}", e))?;
+    Ok(dir)
+}
+
+fn cache_file_name(url: &str) -> String {
+    let name = url
+        .split(['?', '#'])
+        .next()
+        .and_then(|u| u.rsplit('/').next())
+        .filter(|n| !n.is_empty())
+        .unwrap_or("download");
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{:08x}-{}", crc32fast::hash(url.as_bytes()), name)
+}
+
+fn header_string(response: &reqwest::Response, name: header::HeaderName) -> Option<String> {
+    response
+        .headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string())
+}
+
+// Downloads `url` into the download cache and returns the cached file. If the server says the
+// cached copy is still current it is reused without downloading it again.
+pub async fn download_cached(
+    handle: &AppHandle,
+    url: &str,
+    max_bytes: u64,
+    cancel: &CancelToken,
+    mut on_progress: impl FnMut(u64, Option<u64>),
+) -> Result<PathBuf, String> {
+    info!("Downloading file from: {}", url);
+    let store = handle
+        .store("downloads.json")
+        .map_err(|e| format!("Failed to get store: {:?}", e))?;
+    let cached: Option<CachedDownload> = store
+        .get(url)
+        .and_then(|v| serde_json::from_value(v).ok())
+        .filter(|c: &CachedDownload| PathBuf::from(&c.file).exists());
+
+    let mut response = send(url, cached.as_ref()).await?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+        match cached {
+            // Still there, the cache may have been cleaned up since it was checked above
+            Some(cached) if touch(Path::new(&cached.file)) => {
+                info!("Using cached download {}", cached.file);
+                return Ok(PathBuf::from(cached.file));
+            }
+            _ => {
+                warn!("Got 304 Not Modified without a cached copy, downloading again");
+                response = send(url, None).await?;
+            }
+        }
+    }
+    if !response.status().is_success() {
+        error!("Download failed with HTTP status: {}", response.status());
+        return Err(format!(
+            "Failed to download file: HTTP {}",
+            response.status()
+        ));
+    }
+
+    let total = response.content_length();
+    if total.is_some_and(|len| len > max_bytes) {
+        return Err(format!(
+            "The download is too large ({} MB, the limit is {} MB)",
+            total.unwrap_or_default() / 1024 / 1024,
+            max_bytes / 1024 / 1024
+        ));
+    }
+    if let Some(len) = total {
+        info!("Downloading {} bytes", len);
+    }
+
+    let dest = cache_dir(handle)?.join(cache_file_name(url));
+    // Unique per download so two downloads of the same URL don't write into the same file
+    static NEXT_PARTIAL: AtomicU64 = AtomicU64::new(0);
+    let partial = dest.with_file_name(format!(
+        "{}.{}-{}.part",
+        cache_file_name(url),
+        std::process::id(),
+        NEXT_PARTIAL.fetch_add(1, Ordering::SeqCst)
+    ));
+    let mut file = std::fs::File::create(&partial)
+        .map_err(|e| format!("Failed to create {:?}: {}", partial, e))?;
+    let mut done = 0u64;
+    loop {
+        if cancel.is_cancelled() {
+            drop(file);
+            let _ = std::fs::remove_file(&partial);
+            return Err(CANCELLED.to_string());
+        }
+        let chunk = match response.chunk().await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break,
+            Err(e) => {
+                let _ = std::fs::remove_file(&partial);
+                return Err(format!("Failed to read download: {}", e));
+            }
+        };
+        done += chunk.len() as u64;
+        if done > max_bytes {
+            let _ = std::fs::remove_file(&partial);
+            return Err(format!(
+                "The download is larger than the {} MB limit",
+                max_bytes / 1024 / 1024
+            ));
+        }
+        if let Err(e) = file.write_all(&chunk) {
+            drop(file);
+            let _ = std::fs::remove_file(&partial);
+            return Err(format!("Failed to write {:?}: {}", partial, e));
+        }
+        on_progress(done, total);
+    }
+    drop(file);
+    if let Err(e) = std::fs::rename(&partial, &dest) {
+        let _ = std::fs::remove_file(&partial);
+        return Err(format!("Failed to save download: {}", e));
+    }
+
+    let entry = CachedDownload {
+        file: dest.to_string_lossy().to_string(),
+        etag: header_string(&response, header::ETAG),
+        last_modified: header_string(&response, header::LAST_MODIFIED),
+    };
+    match serde_json::to_value(&entry) {
+        Ok(value) => store.set(url, value),
+        Err(e) => warn!("Failed to cache download info: {}", e),
+    }
+
+    info!("Download completed successfully ({} bytes)", done);
+    evict_cache(handle, &dest);
+    Ok(dest)
+}
+
+// Requests `url`, only asking for the body if it changed since `cached` was downloaded
+async fn send(url: &str, cached: Option<&CachedDownload>) -> Result<reqwest::Response, String> {
+    let mut request = reqwest::Client::new().get(url);
+    if let Some(cached) = cached {
+        if let Some(etag) = &cached.etag {
+            request = request.header(header::IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &cached.last_modified {
+            request = request.header(header::IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+    request.send().await.map_err(|e| {
+        error!("Failed to start download: {}", e);
+        format!("Failed to download {}: {}", url, e)
+    })
+}
+
+// Marks a cached file as just used so eviction keeps it longest. False if it is gone.
+fn touch(path: &Path) -> bool {
+    std::fs::File::options()
+        .append(true)
+        .open(path)
+        .and_then(|f| f.set_modified(SystemTime::now()))
+        .is_ok()
+}
+
+// Deletes cached downloads past MAX_CACHE_AGE, then the least recently used until the cache fits
+// in MAX_CACHE_BYTES. `keep` is the download that was just made. Partial files are only deleted
+// once they are too old to still be in progress.
+fn evict_cache(handle: &AppHandle, keep: &Path) {
+    let Ok(dir) = cache_dir(handle) else {
+        return;
+    };
+    let Ok(read_dir) = std::fs::read_dir(&dir) else {
+        return;
+    };
+    let now = SystemTime::now();
+    let mut files: Vec<(PathBuf, SystemTime, u64)> = read_dir
+        .flatten()
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            let modified = metadata.modified().ok()?;
+            metadata
+                .is_file()
+                .then(|| (entry.path(), modified, metadata.len()))
+        })
+        .collect();
+    files.sort_by_key(|(_, modified, _)| *modified);
+
+    let mut total: u64 = files.iter().map(|(_, _, len)| len).sum();
+    for (path, modified, len) in files {
+        if path == keep {
+            continue;
+        }
+        let expired = now.duration_since(modified).unwrap_or_default() > MAX_CACHE_AGE;
+        let partial = path.extension().is_some_and(|e| e == "part");
+        if !expired && (partial || total <= MAX_CACHE_BYTES) {
+            continue;
+        }
+        match std::fs::remove_file(&path) {
+            Ok(()) => {
+                info!("Evicted cached download {:?}", path);
+                total -= len;
+            }
+            Err(e) => warn!("Failed to evict cached download {:?}: {}", path, e),
+        }
+    }
+
+    // download_cached ignores entries whose file is gone, this just keeps the store small
+    if let Ok(store) = handle.store("downloads.json") {
+        for (url, value) in store.entries() {
+            let exists = serde_json::from_value::<CachedDownload>(value)
+                .is_ok_and(|c| Path::new(&c.file).exists());
+            if !exists {
+                store.delete(&url);
+            }
+        }
+    }
+}
+
+// download_cached reporting its progress as "download_progress" events
+pub async fn download_with_progress(
+    handle: &AppHandle,
+    window: &Window,
+    url: &str,
+    cancel: &CancelToken,
+) -> Result<PathBuf, String> {
+    let mut last_emitted = 0;
+    download_cached(
+        handle,
+        url,
+        MAX_DOWNLOAD_BYTES,
+        cancel,
+        |bytes_done, bytes_total| {
+            if bytes_done - last_emitted < 1024 * 1024 && Some(bytes_done) != bytes_total {
+                return;
+            }
+            last_emitted = bytes_done;
+            let _ = window.emit(
+                "download_progress",
+                DownloadProgress {
+                    url,
+                    bytes_done,
+                    bytes_total,
+                },
+            );
+        },
+    )
+    .await
+}
@@ -0,0 +1,521 @@
+use std::path::{Path, PathBuf};
+
+use idevice::{
+    afc::{opcode::AfcFopenMode, AfcClient},
+    house_arrest::HouseArrestClient,
+    IdeviceService,
+};
+use log::{debug, info};
+use serde::Serialize;
+use tauri::{Emitter, State, Window};
+use tokio::sync::OwnedMutexGuard;
+
+use crate::{
+    device::{get_provider, DeviceInfo, DeviceInfoMutex},
+    operation::lock_resources,
+};
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppFileInfo {
+    pub name: String,
+    pub path: String,
+    pub is_dir: bool,
+    pub size: usize,
+    pub blocks: usize,
+    pub created: String,
+    pub modified: String,
+    pub link_target: Option<String>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TransferProgress<'a> {
+    path: &'a str,
+    bytes_done: u64,
+    bytes_total: u64,
+    files_done: usize,
+    files_total: usize,
+}
+
+pub async fn open_container(
+    device: &DeviceInfo,
+    bundle_id: String,
+    full_container: bool,
+) -> Result<AfcClient, String> {
+    let provider = get_provider(device).await?;
+
+    let house_arrest_client = HouseArrestClient::connect(&provider)
+        .await
+        .map_err(|e| format!("Failed to connect to house arrest: {}", e))?;
+
+    if full_container {
+        house_arrest_client
+            .vend_container(bundle_id)
+            .await
+            .map_err(|e| format!("Failed to vend container: {}", e))
+    } else {
+        house_arrest_client
+            .vend_documents(bundle_id)
+            .await
+            .map_err(|e| format!("Failed to vend documents: {}", e))
+    }
+}
+
+fn selected_device(device_state: State<'_, DeviceInfoMutex>) -> Result<DeviceInfo, String> {
+    let device_guard = device_state.lock().unwrap();
+    match &*device_guard {
+        Some(d) => Ok(d.clone()),
+        None => Err("No device selected".to_string()),
+    }
+}
+
+async fn open_selected(
+    device_state: State<'_, DeviceInfoMutex>,
+    bundle_id: String,
+    full_container: Option<bool>,
+) -> Result<AfcClient, String> {
+    let device = selected_device(device_state)?;
+    open_container(&device, bundle_id, full_container.unwrap_or(false)).await
+}
+
+// For commands that change files: waits until no operation is using the device, e.g. restoring
+// a backup into the same container, and keeps it until the guards are dropped
+async fn open_selected_locked(
+    device_state: State<'_, DeviceInfoMutex>,
+    bundle_id: String,
+    full_container: Option<bool>,
+) -> Result<(AfcClient, Vec<OwnedMutexGuard<()>>), String> {
+    let device = selected_device(device_state)?;
+    let locks = lock_resources(Some(&device.uuid), None).await;
+    let afc_client = open_container(&device, bundle_id, full_container.unwrap_or(false)).await?;
+    Ok((afc_client, locks))
+}
+
+pub fn join_remote(base: &str, name: &str) -> String {
+    format!(
+        "{}/{}",
+        base.trim_end_matches('/'),
+        name.trim_start_matches('/')
+    )
+}
+
+pub async fn file_info(afc_client: &mut AfcClient, path: &str) -> Result<AppFileInfo, String> {
+    let info = afc_client
+        .get_file_info(path)
+        .await
+        .map_err(|e| format!("Failed to get file info for {}: {}", path, e))?;
+
+    Ok(AppFileInfo {
+        name: path
+            .trim_end_matches('/')
+            .rsplit('/')
+            .next()
+            .unwrap_or_default()
+            .to_string(),
+        path: path.to_string(),
+        is_dir: info.st_ifmt == "S_IFDIR",
+        size: info.size,
+        blocks: info.blocks,
+        created: info.creation.to_string(),
+        modified: info.modified.to_string(),
+        link_target: info.st_link_target,
+    })
+}
+
+pub async fn list_dir(afc_client: &mut AfcClient, path: &str) -> Result<Vec<AppFileInfo>, String> {
+    let names = afc_client
+        .list_dir(path)
+        .await
+        .map_err(|e| format!("Failed to list {}: {}", path, e))?;
+
+    let mut entries = Vec::new();
+    for name in names {
+        if name == "." || name == ".." {
+            continue;
+        }
+        entries.push(file_info(afc_client, &join_remote(path, &name)).await?);
+    }
+    Ok(entries)
+}
+
+pub async fn write_file(
+    afc_client: &mut AfcClient,
+    path: &str,
+    contents: &[u8],
+) -> Result<(), String> {
+    let mut file = afc_client
+        .open(path, AfcFopenMode::Wr)
+        .await
+        .map_err(|e| format!("Failed to open {} on device: {}", path, e))?;
+    file.write_entire(contents)
+        .await
+        .map_err(|e| format!("Failed to write {}: {}", path, e))?;
+    file.close()
+        .await
+        .map_err(|e| format!("Failed to close {}: {}", path, e))
+}
+
+// write_file for large files, reporting the percentage written after every chunk
+pub async fn write_file_with_progress(
+    afc_client: &mut AfcClient,
+    path: &str,
+    contents: &[u8],
+    mut on_progress: impl FnMut(u64),
+) -> Result<(), String> {
+    const CHUNK_SIZE: usize = 1024 * 1024;
+    let mut file = afc_client
+        .open(path, AfcFopenMode::Wr)
+        .await
+        .map_err(|e| format!("Failed to open {} on device: {}", path, e))?;
+    let mut written = 0;
+    for chunk in contents.chunks(CHUNK_SIZE) {
+        file.write_entire(chunk)
+            .await
+            .map_err(|e| format!("Failed to write {}: {}", path, e))?;
+        written += chunk.len();
+        on_progress((written * 100 / contents.len()) as u64);
+    }
+    file.close()
+        .await
+        .map_err(|e| format!("Failed to close {}: {}", path, e))
+}
+
+pub async fn read_file(afc_client: &mut AfcClient, path: &str) -> Result<Vec<u8>, String> {
+    let mut file = afc_client
+        .open(path, AfcFopenMode::RdOnly)
+        .await
+        .map_err(|e| format!("Failed to open {} on device: {}", path, e))?;
+    let contents = file
+        .read_entire()
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    file.close()
+        .await
+        .map_err(|e| format!("Failed to close {}: {}", path, e))?;
+    Ok(contents)
+}
+
+// Directories relative to the root, parents first, and files as (local path, relative path)
+pub type LocalTree = (Vec<String>, Vec<(PathBuf, String)>);
+
+// Returns every file below `root` along with the directories that need to exist on the other
+// side. Links to directories are skipped rather than followed, so a link loop can't recurse
+// forever.
+pub fn walk_local(root: &Path) -> Result<LocalTree, String> {
+    let mut dirs = Vec::new();
+    let mut files = Vec::new();
+    let mut stack = vec![(root.to_path_buf(), String::new())];
+
+    while let Some((dir, rel)) = stack.pop() {
+        let read_dir =
+            std::fs::read_dir(&dir).map_err(|e| format!("Failed to read {:?}: {}", dir, e))?;
+        for entry in read_dir {
+            let entry = entry.map_err(|e| format!("Failed to read {:?}: {}", dir, e))?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            let child_rel = if rel.is_empty() {
+                name
+            } else {
+                format!("{}/{}", rel, name)
+            };
+            let file_type = entry
+                .file_type()
+                .map_err(|e| format!("Failed to read {:?}: {}", entry.path(), e))?;
+            if file_type.is_symlink() && entry.path().is_dir() {
+                debug!("Skipping directory link {:?}", entry.path());
+            } else if file_type.is_dir() {
+                dirs.push(child_rel.clone());
+                stack.push((entry.path(), child_rel));
+            } else {
+                files.push((entry.path(), child_rel));
+            }
+        }
+    }
+
+    dirs.sort();
+    Ok((dirs, files))
+}
+
+// Same as walk_local, but for a directory in the vended container. Files carry their size so
+// progress can be reported in bytes.
+async fn walk_remote(
+    afc_client: &mut AfcClient,
+    root: &str,
+) -> Result<(Vec<String>, Vec<(String, u64)>), String> {
+    let mut dirs = Vec::new();
+    let mut files = Vec::new();
+    let mut stack = vec![String::new()];
+
+    while let Some(rel) = stack.pop() {
+        let dir = if rel.is_empty() {
+            root.to_string()
+        } else {
+            join_remote(root, &rel)
+        };
+        for entry in list_dir(afc_client, &dir).await? {
+            let child_rel = if rel.is_empty() {
+                entry.name.clone()
+            } else {
+                format!("{}/{}", rel, entry.name)
+            };
+            if entry.is_dir {
+                dirs.push(child_rel.clone());
+                stack.push(child_rel);
+            } else {
+                files.push((child_rel, entry.size as u64));
+            }
+        }
+    }
+
+    dirs.sort();
+    Ok((dirs, files))
+}
+
+fn emit_progress(window: &Window, transfer_id: &str, progress: TransferProgress) {
+    let _ = window.emit(&format!("transfer_{}", transfer_id), progress);
+}
+
+pub async fn upload(
+    afc_client: &mut AfcClient,
+    local_path: &Path,
+    remote_path: &str,
+    mut on_progress: impl FnMut(&str, u64, u64, usize, usize),
+) -> Result<(), String> {
+    if !local_path.is_dir() {
+        let contents = tokio::fs::read(local_path)
+            .await
+            .map_err(|e| format!("Failed to read {:?}: {}", local_path, e))?;
+        let total = contents.len() as u64;
+        on_progress(remote_path, 0, total, 0, 1);
+        write_file(afc_client, remote_path, &contents).await?;
+        on_progress(remote_path, total, total, 1, 1);
+        return Ok(());
+    }
+
+    let (dirs, files) = walk_local(local_path)?;
+    let bytes_total = files
+        .iter()
+        .map(|(p, _)| std::fs::metadata(p).map(|m| m.len()).unwrap_or(0))
+        .sum();
+
+    afc_client
+        .mk_dir(remote_path)
+        .await
+        .map_err(|e| format!("Failed to create {}: {}", remote_path, e))?;
+    for dir in &dirs {
+        let remote_dir = join_remote(remote_path, dir);
+        afc_client
+            .mk_dir(&remote_dir)
+            .await
+            .map_err(|e| format!("Failed to create {}: {}", remote_dir, e))?;
+    }
+
+    let mut bytes_done = 0;
+    for (i, (local, rel)) in files.iter().enumerate() {
+        let remote = join_remote(remote_path, rel);
+        debug!("Uploading {:?} to {}", local, remote);
+        let contents = tokio::fs::read(local)
+            .await
+            .map_err(|e| format!("Failed to read {:?}: {}", local, e))?;
+        write_file(afc_client, &remote, &contents).await?;
+        bytes_done += contents.len() as u64;
+        on_progress(&remote, bytes_done, bytes_total, i + 1, files.len());
+    }
+
+    Ok(())
+}
+
+pub async fn download(
+    afc_client: &mut AfcClient,
+    remote_path: &str,
+    local_path: &Path,
+    mut on_progress: impl FnMut(&str, u64, u64, usize, usize),
+) -> Result<(), String> {
+    let info = file_info(afc_client, remote_path).await?;
+    if !info.is_dir {
+        let total = info.size as u64;
+        on_progress(remote_path, 0, total, 0, 1);
+        let contents = read_file(afc_client, remote_path).await?;
+        tokio::fs::write(local_path, &contents)
+            .await
+            .map_err(|e| format!("Failed to write {:?}: {}", local_path, e))?;
+        on_progress(remote_path, total, total, 1, 1);
+        return Ok(());
+    }
+
+    let (dirs, files) = walk_remote(afc_client, remote_path).await?;
+    let bytes_total = files.iter().map(|(_, size)| size).sum();
+
+    tokio::fs::create_dir_all(local_path)
+        .await
+        .map_err(|e| format!("Failed to create {:?}: {}", local_path, e))?;
+    for dir in &dirs {
+        let local_dir = local_path.join(dir);
+        tokio::fs::create_dir_all(&local_dir)
+            .await
+            .map_err(|e| format!("Failed to create {:?}: {}", local_dir, e))?;
+    }
+
+    let mut bytes_done = 0;
+    for (i, (rel, _)) in files.iter().enumerate() {
+        let remote = join_remote(remote_path, rel);
+        debug!("Downloading {} to {:?}", remote, local_path.join(rel));
+        let contents = read_file(afc_client, &remote).await?;
+        tokio::fs::write(local_path.join(rel), &contents)
+            .await
+            .map_err(|e| format!("Failed to write {:?}: {}", local_path.join(rel), e))?;
+        bytes_done += contents.len() as u64;
+        on_progress(&remote, bytes_done, bytes_total, i + 1, files.len());
+    }
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn list_app_files(
+    device_state: State<'_, DeviceInfoMutex>,
+    bundle_id: String,
+    path: String,
+    full_container: Option<bool>,
+) -> Result<Vec<AppFileInfo>, String> {
+    let mut afc_client = open_selected(device_state, bundle_id, full_container).await?;
+    list_dir(&mut afc_client, &path).await
+}
+
+#[tauri::command]
+pub async fn app_file_info(
+    device_state: State<'_, DeviceInfoMutex>,
+    bundle_id: String,
+    path: String,
+    full_container: Option<bool>,
+) -> Result<AppFileInfo, String> {
+    let mut afc_client = open_selected(device_state, bundle_id, full_container).await?;
+    file_info(&mut afc_client, &path).await
+}
+
+#[tauri::command]
+pub async fn upload_app_file(
+    window: Window,
+    device_state: State<'_, DeviceInfoMutex>,
+    bundle_id: String,
+    local_path: String,
+    remote_path: String,
+    transfer_id: String,
+    full_container: Option<bool>,
+) -> Result<(), String> {
+    info!(
+        "Uploading {} to {} in {}",
+        local_path, remote_path, bundle_id
+    );
+    let (mut afc_client, _locks) =
+        open_selected_locked(device_state, bundle_id, full_container).await?;
+    upload(
+        &mut afc_client,
+        Path::new(&local_path),
+        &remote_path,
+        |path, bytes_done, bytes_total, files_done, files_total| {
+            emit_progress(
+                &window,
+                &transfer_id,
+                TransferProgress {
+                    path,
+                    bytes_done,
+                    bytes_total,
+                    files_done,
+                    files_total,
+                },
+            )
+        },
+    )
+    .await
+}
+
+#[tauri::command]
+pub async fn download_app_file(
+    window: Window,
+    device_state: State<'_, DeviceInfoMutex>,
+    bundle_id: String,
+    remote_path: String,
+    local_path: String,
+    transfer_id: String,
+    full_container: Option<bool>,
+) -> Result<(), String> {
+    info!(
+        "Downloading {} from {} to {}",
+        remote_path, bundle_id, local_path
+    );
+    let mut afc_client = open_selected(device_state, bundle_id, full_container).await?;
+    download(
+        &mut afc_client,
+        &remote_path,
+        Path::new(&local_path),
+        |path, bytes_done, bytes_total, files_done, files_total| {
+            emit_progress(
+                &window,
+                &transfer_id,
+                TransferProgress {
+                    path,
+                    bytes_done,
+                    bytes_total,
+                    files_done,
+                    files_total,
+                },
+            )
+        },
+    )
+    .await
+}
+
+#[tauri::command]
+pub async fn rename_app_file(
+    device_state: State<'_, DeviceInfoMutex>,
+    bundle_id: String,
+    from: String,
+    to: String,
+    full_container: Option<bool>,
+) -> Result<(), String> {
+    let (mut afc_client, _locks) =
+        open_selected_locked(device_state, bundle_id, full_container).await?;
+    afc_client
+        .rename(&from, &to)
+        .await
+        .map_err(|e| format!("Failed to rename {} to {}: {}", from, to, e))
+}
+
+#[tauri::command]
+pub async fn mkdir_app_file(
+    device_state: State<'_, DeviceInfoMutex>,
+    bundle_id: String,
+    path: String,
+    full_container: Option<bool>,
+) -> Result<(), String> {
+    let (mut afc_client, _locks) =
+        open_selected_locked(device_state, bundle_id, full_container).await?;
+    afc_client
+        .mk_dir(&path)
+        .await
+        .map_err(|e| format!("Failed to create {}: {}", path, e))
+}
+
+#[tauri::command]
+pub async fn delete_app_file(
+    device_state: State<'_, DeviceInfoMutex>,
+    bundle_id: String,
+    path: String,
+    full_container: Option<bool>,
+) -> Result<(), String> {
+    let (mut afc_client, _locks) =
+        open_selected_locked(device_state, bundle_id, full_container).await?;
+    let info = file_info(&mut afc_client, &path).await?;
+    if info.is_dir {
+        afc_client
+            .remove_all(&path)
+            .await
+            .map_err(|e| format!("Failed to delete {}: {}", path, e))
+    } else {
+        afc_client
+            .remove(&path)
+            .await
+            .map_err(|e| format!("Failed to delete {}: {}", path, e))
+    }
+}
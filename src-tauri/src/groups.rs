@@ -0,0 +1,286 @@
+use std::path::Path;
+
+use isideload::developer_session::{AppId, DeveloperDeviceType, DeveloperSession, DeveloperTeam};
+use log::{debug, info};
+
+use crate::ipa::{macho_entitlements, plist_string, read_info_plist};
+
+const KEYCHAIN_GROUPS: &str = "keychain-access-groups";
+const APP_GROUPS: &str = "com.apple.security.application-groups";
+
+// Strips the team ID (or Xcode's placeholder) the app was built with from a keychain group
+fn keychain_group_suffix(group: &str) -> &str {
+    if let Some(rest) = group.strip_prefix("$(AppIdentifierPrefix)") {
+        return rest;
+    }
+    match group.split_once('.') {
+        Some((prefix, rest))
+            if prefix.len() == 10 && prefix.chars().all(|c| c.is_ascii_alphanumeric()) =>
+        {
+            rest
+        }
+        _ => group,
+    }
+}
+
+fn string_array(dict: &plist::Dictionary, key: &str) -> Vec<String> {
+    dict.get(key)
+        .and_then(|v| v.as_array())
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|i| i.as_string().map(|s| s.to_string()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn requested_entitlements(app_dir: &Path) -> Result<Option<plist::Dictionary>, String> {
+    let info = read_info_plist(app_dir)?;
+    let executable = plist_string(&info, "CFBundleExecutable")
+        .ok_or("Info.plist has no CFBundleExecutable".to_string())?;
+    let binary = std::fs::read(app_dir.join(executable))
+        .map_err(|e| format!("Failed to read app executable: {}", e))?;
+    Ok(macho_entitlements(&binary))
+}
+
+// Bundle IDs LiveContainer is released under
+const LIVE_CONTAINER_BUNDLE_IDS: &[&str] = &["com.kdt.livecontainer"];
+// Prefix of the keychain groups LiveContainer gives its containers, kept by forks that change
+// the bundle ID
+const LIVE_CONTAINER_KEYCHAIN_PREFIX: &str = "com.kdt.livecontainer.";
+
+// LiveContainer keeps each container's keychain in its own group and shares data with SideStore
+// through app groups. Free provisioning profiles only allow "<team>.*", so unless the groups it
+// asks for are signed in explicitly, every app it runs ends up in the same keychain.
+pub fn is_live_container(bundle_id: &str, app_dir: &Path) -> bool {
+    if LIVE_CONTAINER_BUNDLE_IDS.contains(&bundle_id) {
+        return true;
+    }
+    let requested = requested_entitlements(app_dir).ok().flatten();
+    requested.is_some_and(|requested| {
+        string_array(&requested, KEYCHAIN_GROUPS)
+            .iter()
+            .any(|g| keychain_group_suffix(g).starts_with(LIVE_CONTAINER_KEYCHAIN_PREFIX))
+    })
+}
+
+// The groups one signed bundle asks for, as written in the app
+pub struct GroupRequest {
+    pub app_id: AppId,
+    pub keychain_groups: Vec<String>,
+    pub app_groups: Vec<String>,
+}
+
+impl GroupRequest {
+    // Reads the groups from the entitlements of the bundle's executable
+    pub fn from_bundle(bundle_dir: &Path, app_id: AppId) -> Result<GroupRequest, String> {
+        let requested = requested_entitlements(bundle_dir)?.unwrap_or_default();
+        Ok(GroupRequest {
+            app_id,
+            keychain_groups: string_array(&requested, KEYCHAIN_GROUPS),
+            app_groups: string_array(&requested, APP_GROUPS),
+        })
+    }
+}
+
+impl GroupRequest {
+    // Moves the groups set in `entitlements`, e.g. by a preset, into the request so they are
+    // registered and renamed like the ones the app asks for
+    pub fn take_from(&mut self, entitlements: &mut plist::Dictionary) {
+        for (key, groups) in [
+            (KEYCHAIN_GROUPS, &mut self.keychain_groups),
+            (APP_GROUPS, &mut self.app_groups),
+        ] {
+            for group in string_array(entitlements, key) {
+                push_unique(groups, group);
+            }
+            entitlements.remove(key);
+        }
+    }
+}
+
+fn push_unique(items: &mut Vec<String>, item: String) {
+    if !items.contains(&item) {
+        items.push(item);
+    }
+}
+
+// Puts the groups allocated in `extra` in front of the ones the profile already grants, so
+// signing with them adds to the profile's groups instead of replacing them. Allocated groups stay
+// first because the first keychain group is the app's default one.
+pub fn merge_profile_groups(profile: &plist::Dictionary, extra: &mut plist::Dictionary) {
+    for key in [KEYCHAIN_GROUPS, APP_GROUPS] {
+        if !extra.contains_key(key) {
+            continue;
+        }
+        let mut groups = string_array(extra, key);
+        for group in string_array(profile, key) {
+            push_unique(&mut groups, group);
+        }
+        extra.insert(
+            key.to_string(),
+            plist::Value::Array(groups.into_iter().map(plist::Value::String).collect()),
+        );
+    }
+}
+
+// Registers the app groups the bundles request under the team, assigns each to the App ID of
+// the bundle asking for it, and returns the entitlements to sign with. The first request is
+// the main app's. Every bundle is signed with the main App ID's profile, so it gets all of the
+// groups too. Runs before the profile is downloaded so it already contains them.
+pub async fn allocate_groups(
+    dev_session: &DeveloperSession,
+    team: &DeveloperTeam,
+    requests: &[GroupRequest],
+) -> Result<Option<plist::Dictionary>, String> {
+    let Some(main) = requests.first() else {
+        return Ok(None);
+    };
+    // Group identifiers are global, so they get the team ID appended like the bundle IDs do
+    let team_app_group = |g: &String| {
+        if g.ends_with(&format!(".{}", team.team_id)) {
+            g.clone()
+        } else {
+            format!("{}.{}", g, team.team_id)
+        }
+    };
+
+    let mut keychain_groups = Vec::new();
+    let mut app_groups = Vec::new();
+    // (group, App IDs to assign it to)
+    let mut assignments: Vec<(String, Vec<&AppId>)> = Vec::new();
+    for request in requests {
+        for group in &request.keychain_groups {
+            push_unique(
+                &mut keychain_groups,
+                format!("{}.{}", team.team_id, keychain_group_suffix(group)),
+            );
+        }
+        for group in request.app_groups.iter().map(team_app_group) {
+            push_unique(&mut app_groups, group.clone());
+            let app_ids = match assignments.iter_mut().find(|(g, _)| *g == group) {
+                Some((_, app_ids)) => app_ids,
+                None => {
+                    assignments.push((group, vec![&main.app_id]));
+                    &mut assignments.last_mut().unwrap().1
+                }
+            };
+            if !app_ids
+                .iter()
+                .any(|a| a.app_id_id == request.app_id.app_id_id)
+            {
+                app_ids.push(&request.app_id);
+            }
+        }
+    }
+    if keychain_groups.is_empty() && app_groups.is_empty() {
+        debug!("App requests no groups, nothing to allocate");
+        return Ok(None);
+    }
+
+    if !assignments.is_empty() {
+        let existing_groups = dev_session
+            .list_application_groups(DeveloperDeviceType::Ios, team)
+            .await
+            .map_err(|e| format!("Failed to list app groups: {:?}", e))?;
+        for (identifier, app_ids) in &assignments {
+            let group = match existing_groups.iter().find(|g| &g.identifier == identifier) {
+                Some(group) => group.clone(),
+                None => {
+                    info!("Registering app group {}", identifier);
+                    let name = format!("iloader {}", identifier.replace('.', " "));
+                    dev_session
+                        .add_application_group(DeveloperDeviceType::Ios, team, identifier, &name)
+                        .await
+                        .map_err(|e| {
+                            format!("Failed to register app group {}: {:?}", identifier, e)
+                        })?
+                }
+            };
+            for app_id in app_ids {
+                dev_session
+                    .assign_application_group_to_app_id(
+                        DeveloperDeviceType::Ios,
+                        team,
+                        app_id,
+                        &group,
+                    )
+                    .await
+                    .map_err(|e| {
+                        format!(
+                            "Failed to assign app group {} to {}: {:?}",
+                            identifier, app_id.identifier, e
+                        )
+                    })?;
+            }
+        }
+    }
+
+    info!(
+        "Signing with {} keychain groups and {} app groups",
+        keychain_groups.len(),
+        app_groups.len()
+    );
+    let mut entitlements = plist::Dictionary::new();
+    let to_array =
+        |items: &[String]| plist::Value::Array(items.iter().cloned().map(Into::into).collect());
+    if !keychain_groups.is_empty() {
+        entitlements.insert(KEYCHAIN_GROUPS.to_string(), to_array(&keychain_groups));
+    }
+    if !app_groups.is_empty() {
+        entitlements.insert(APP_GROUPS.to_string(), to_array(&app_groups));
+    }
+    Ok(Some(entitlements))
+}
+
+// SideStore and LiveContainer find their shared group through ALTAppGroups, which has to match
+// the renamed groups
+pub fn update_alt_app_groups(info: &mut plist::Dictionary, entitlements: &plist::Dictionary) {
+    let groups = string_array(entitlements, APP_GROUPS);
+    if groups.is_empty() || !info.contains_key("ALTAppGroups") {
+        return;
+    }
+    info.insert(
+        "ALTAppGroups".to_string(),
+        plist::Value::Array(groups.into_iter().map(Into::into).collect()),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn groups(items: &[&str]) -> plist::Value {
+        plist::Value::Array(
+            items
+                .iter()
+                .map(|i| plist::Value::String(i.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn merges_allocated_groups_with_the_profile() {
+        let mut profile = plist::Dictionary::new();
+        profile.insert(
+            APP_GROUPS.to_string(),
+            groups(&["group.com.example.app.TEAM"]),
+        );
+        profile.insert(KEYCHAIN_GROUPS.to_string(), groups(&["TEAM.*"]));
+        let mut extra = plist::Dictionary::new();
+        extra.insert(
+            APP_GROUPS.to_string(),
+            groups(&["group.shared.TEAM", "group.com.example.app.TEAM"]),
+        );
+
+        merge_profile_groups(&profile, &mut extra);
+
+        assert_eq!(
+            string_array(&extra, APP_GROUPS),
+            ["group.shared.TEAM", "group.com.example.app.TEAM"]
+        );
+        // Keys nothing was allocated for are left to the profile
+        assert!(!extra.contains_key(KEYCHAIN_GROUPS));
+    }
+}
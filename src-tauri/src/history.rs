@@ -0,0 +1,216 @@
+use std::sync::Mutex;
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::AppHandle;
+use tauri_plugin_store::StoreExt;
+
+use crate::inventory::now;
+
+// Older runs are dropped once either limit is reached
+const MAX_ENTRIES: usize = 500;
+const MAX_AGE_SECS: u64 = 30 * 24 * 60 * 60;
+
+// Held while the history is read and written back, so concurrent runs don't drop each other's
+// updates
+static HISTORY_LOCK: Mutex<()> = Mutex::new(());
+
+// Parameters whose names contain any of these are never written to disk
+const SECRET_KEYS: [&str; 4] = ["password", "secret", "token", "key"];
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryStep {
+    pub id: String,
+    pub started_at: u64,
+    pub finished_at: Option<u64>,
+    // "running", "finished", "failed" or "cancelled"
+    pub status: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    pub id: String,
+    pub kind: String,
+    pub params: Value,
+    pub udid: Option<String>,
+    pub apple_id: Option<String>,
+    pub started_at: u64,
+    pub finished_at: Option<u64>,
+    // "running", "succeeded", "failed", "cancelled" or "interrupted" if it stopped without
+    // reporting why
+    pub status: String,
+    pub steps: Vec<HistoryStep>,
+    pub error: Option<String>,
+    pub warnings: Vec<String>,
+}
+
+impl HistoryEntry {
+    pub fn new(kind: &str, run_id: u64) -> HistoryEntry {
+        let started_at = now();
+        HistoryEntry {
+            id: format!("{}-{}", started_at, run_id),
+            kind: kind.to_string(),
+            params: Value::Null,
+            udid: None,
+            apple_id: None,
+            started_at,
+            finished_at: None,
+            status: "running".to_string(),
+            steps: vec![],
+            error: None,
+            warnings: vec![],
+        }
+    }
+
+    pub fn start_step(&mut self, id: &str) {
+        self.steps.push(HistoryStep {
+            id: id.to_string(),
+            started_at: now(),
+            finished_at: None,
+            status: "running".to_string(),
+        });
+    }
+
+    // Ends the latest run of step `id`, recording it even if it was never started
+    pub fn end_step(&mut self, id: &str, status: &str) {
+        if !self
+            .steps
+            .iter()
+            .rev()
+            .any(|s| s.id == id && s.status == "running")
+        {
+            self.start_step(id);
+        }
+        if let Some(step) = self
+            .steps
+            .iter_mut()
+            .rev()
+            .find(|s| s.id == id && s.status == "running")
+        {
+            step.finished_at = Some(now());
+            step.status = status.to_string();
+        }
+    }
+
+    pub fn finish(&mut self) {
+        if self.status == "running" {
+            self.status = if self.steps.iter().all(|s| s.status == "finished") {
+                "succeeded".to_string()
+            } else {
+                "interrupted".to_string()
+            };
+        }
+        self.finished_at = Some(now());
+    }
+}
+
+pub fn redact(params: Value) -> Value {
+    match params {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(k, v)| {
+                    let lower = k.to_lowercase();
+                    if SECRET_KEYS.iter().any(|s| lower.contains(s)) {
+                        (k, Value::String("<redacted>".to_string()))
+                    } else {
+                        (k, redact(v))
+                    }
+                })
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.into_iter().map(redact).collect()),
+        other => other,
+    }
+}
+
+fn load_history(handle: &AppHandle) -> Result<Vec<HistoryEntry>, String> {
+    let store = handle
+        .store("history.json")
+        .map_err(|e| format!("Failed to get store: {:?}", e))?;
+    Ok(store
+        .get("history")
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default())
+}
+
+fn save_history(handle: &AppHandle, entries: &[HistoryEntry]) -> Result<(), String> {
+    let store = handle
+        .store("history.json")
+        .map_err(|e| format!("Failed to get store: {:?}", e))?;
+    store.set(
+        "history",
+        serde_json::to_value(entries).map_err(|e| e.to_string())?,
+    );
+    Ok(())
+}
+
+// Adds or replaces the run and drops runs past the retention limits. Entries are kept newest
+// first.
+pub fn record_run(handle: &AppHandle, entry: &HistoryEntry) {
+    let _lock = HISTORY_LOCK.lock().unwrap();
+    let res = load_history(handle).and_then(|mut entries| {
+        entries.retain(|e| e.id != entry.id);
+        entries.insert(0, entry.clone());
+        let cutoff = now().saturating_sub(MAX_AGE_SECS);
+        entries.retain(|e| e.started_at >= cutoff);
+        entries.truncate(MAX_ENTRIES);
+        save_history(handle, &entries)
+    });
+    if let Err(e) = res {
+        warn!("Failed to record {} in history: {}", entry.kind, e);
+    }
+}
+
+// Runs still marked as running when the app starts were cut off by it quitting or crashing
+pub fn mark_interrupted(handle: &AppHandle) {
+    let _lock = HISTORY_LOCK.lock().unwrap();
+    let res = load_history(handle).and_then(|mut entries| {
+        let mut changed = false;
+        for entry in entries.iter_mut().filter(|e| e.status == "running") {
+            entry.status = "interrupted".to_string();
+            changed = true;
+        }
+        if changed {
+            save_history(handle, &entries)
+        } else {
+            Ok(())
+        }
+    });
+    if let Err(e) = res {
+        warn!("Failed to update the history: {}", e);
+    }
+}
+
+#[tauri::command]
+pub fn list_history(
+    handle: AppHandle,
+    kind: Option<String>,
+    limit: Option<usize>,
+) -> Result<Vec<HistoryEntry>, String> {
+    let mut entries = load_history(&handle)?;
+    if let Some(kind) = kind {
+        entries.retain(|e| e.kind == kind);
+    }
+    if let Some(limit) = limit {
+        entries.truncate(limit);
+    }
+    Ok(entries)
+}
+
+#[tauri::command]
+pub fn get_history_entry(handle: AppHandle, id: String) -> Result<HistoryEntry, String> {
+    load_history(&handle)?
+        .into_iter()
+        .find(|e| e.id == id)
+        .ok_or(format!("No history entry {}", id))
+}
+
+#[tauri::command]
+pub fn clear_history(handle: AppHandle) -> Result<(), String> {
+    info!("Clearing operation history");
+    let _lock = HISTORY_LOCK.lock().unwrap();
+    save_history(&handle, &[])
+}
@@ -0,0 +1,244 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use log::info;
+use openssl::{
+    pkcs12::Pkcs12,
+    pkey::{PKey, Private},
+    x509::X509,
+};
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::{
+    account::{get_account, get_certificates},
+    apps::normalize_serial,
+    inventory::now,
+    signing::{store_dir, MACHINE_NAME},
+};
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IdentityInfo {
+    pub apple_id: String,
+    pub common_name: Option<String>,
+    pub serial_number: String,
+    pub expires: String,
+}
+
+// isideload 0.1.22 (CertificateIdentity::new) keeps the signing key and certificate of each
+// Apple ID in "<store dir>/keys/<lowercase hex sha1 of the Apple ID>/{key.pem,cert.pem}". It
+// reuses key.pem and picks the account's certificate named MACHINE_NAME whose public key matches
+// it instead of requesting a new one.
+fn identity_dir(store_dir: &Path, apple_id: &str) -> PathBuf {
+    let hash: String = openssl::sha::sha1(apple_id.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    store_dir.join("keys").join(hash)
+}
+
+fn account_identity_dir(handle: &AppHandle, apple_id: &str) -> Result<PathBuf, String> {
+    Ok(identity_dir(&store_dir(handle)?, apple_id))
+}
+
+// The key is written readable by the current user only
+fn write_key(path: &Path, pem: &[u8]) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // The mode only applies to new files
+        if path.exists() {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    options.open(path)?.write_all(pem)
+}
+
+fn identity_info(apple_id: &str, cert: &X509) -> Result<IdentityInfo, String> {
+    let serial = cert
+        .serial_number()
+        .to_bn()
+        .and_then(|bn| bn.to_hex_str())
+        .map_err(|e| format!("Failed to read certificate serial: {}", e))?;
+    Ok(IdentityInfo {
+        apple_id: apple_id.to_string(),
+        common_name: cert
+            .subject_name()
+            .entries_by_nid(openssl::nid::Nid::COMMONNAME)
+            .next()
+            .and_then(|e| e.data().as_utf8().ok())
+            .map(|s| s.to_string()),
+        serial_number: serial.to_string(),
+        expires: cert.not_after().to_string(),
+    })
+}
+
+fn read_identity(handle: &AppHandle, apple_id: &str) -> Result<(PKey<Private>, X509), String> {
+    let dir = account_identity_dir(handle, apple_id)?;
+    let key = std::fs::read(dir.join("key.pem"))
+        .map_err(|_| "No signing identity yet, sideload an app first".to_string())?;
+    let cert = std::fs::read(dir.join("cert.pem"))
+        .map_err(|_| "No signing certificate yet, sideload an app first".to_string())?;
+    let key = PKey::private_key_from_pem(&key)
+        .map_err(|e| format!("Failed to read signing key: {}", e))?;
+    let cert =
+        X509::from_pem(&cert).map_err(|e| format!("Failed to read signing certificate: {}", e))?;
+    Ok((key, cert))
+}
+
+#[tauri::command]
+pub fn current_identity(handle: AppHandle) -> Result<Option<IdentityInfo>, String> {
+    let apple_id = get_account()?.apple_id.clone();
+    match read_identity(&handle, &apple_id) {
+        Ok((_, cert)) => Ok(Some(identity_info(&apple_id, &cert)?)),
+        Err(_) => Ok(None),
+    }
+}
+
+// Writes the logged in account's key and certificate to a .p12 protected by `password`
+#[tauri::command]
+pub fn export_identity(handle: AppHandle, path: String, password: String) -> Result<(), String> {
+    if password.is_empty() {
+        return Err("A password is required to export the signing identity".to_string());
+    }
+    let apple_id = get_account()?.apple_id.clone();
+    let (key, cert) = read_identity(&handle, &apple_id)?;
+    let p12 = Pkcs12::builder()
+        .name("iloader")
+        .pkey(&key)
+        .cert(&cert)
+        .build2(&password)
+        .map_err(|e| format!("Failed to create .p12: {}", e))?;
+    let der = p12
+        .to_der()
+        .map_err(|e| format!("Failed to create .p12: {}", e))?;
+    std::fs::write(&path, der).map_err(|e| format!("Failed to write {}: {}", path, e))?;
+    info!("Exported signing identity of {} to {}", apple_id, path);
+    Ok(())
+}
+
+// Replaces the logged in account's identity with one exported on another machine, so signing
+// here reuses its certificate instead of using up another one. An existing identity is only
+// replaced with `replace` set, and is kept in a "replaced-<time>" folder next to it.
+#[tauri::command]
+pub async fn import_identity(
+    handle: AppHandle,
+    path: String,
+    password: String,
+    replace: Option<bool>,
+) -> Result<IdentityInfo, String> {
+    let apple_id = get_account()?.apple_id.clone();
+    let data = std::fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let parsed = Pkcs12::from_der(&data)
+        .and_then(|p12| p12.parse2(&password))
+        .map_err(|_| "The file is not a .p12 or the password is wrong".to_string())?;
+    let (Some(key), Some(cert)) = (parsed.pkey, parsed.cert) else {
+        return Err("The .p12 must contain a private key and a certificate".to_string());
+    };
+    let matches = cert
+        .public_key()
+        .map(|public| key.public_eq(&public))
+        .unwrap_or(false);
+    if !matches {
+        return Err("The certificate in the .p12 doesn't belong to its private key".to_string());
+    }
+
+    let info = identity_info(&apple_id, &cert)?;
+    // isideload only reuses an active certificate of this account made under its machine name,
+    // anything else would make the next sideload quietly request a new one
+    let certificates = get_certificates().await?;
+    let Some(on_account) = certificates
+        .iter()
+        .find(|c| normalize_serial(&c.serial_number) == normalize_serial(&info.serial_number))
+    else {
+        return Err(format!(
+            "Certificate {} is not an active certificate of {}",
+            info.serial_number, apple_id
+        ));
+    };
+    if on_account.machine_name != MACHINE_NAME {
+        return Err(format!(
+            "Certificate {} was created by \"{}\", only certificates created by {} can be reused",
+            info.serial_number, on_account.machine_name, MACHINE_NAME
+        ));
+    }
+
+    let dir = account_identity_dir(&handle, &apple_id)?;
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {:?}: {}", dir, e))?;
+    let existing = ["key.pem", "cert.pem"]
+        .iter()
+        .filter(|name| dir.join(name).exists())
+        .collect::<Vec<_>>();
+    if !existing.is_empty() {
+        if !replace.unwrap_or(false) {
+            return Err(format!(
+                "{} already has a signing identity on this computer. Import again with replace \
+                 set to overwrite it.",
+                apple_id
+            ));
+        }
+        let backup = dir.join(format!("replaced-{}", now()));
+        std::fs::create_dir_all(&backup)
+            .map_err(|e| format!("Failed to back up the current identity: {}", e))?;
+        for name in existing {
+            std::fs::rename(dir.join(name), backup.join(name))
+                .map_err(|e| format!("Failed to back up the current identity: {}", e))?;
+        }
+        info!(
+            "Moved the previous identity of {} to {:?}",
+            apple_id, backup
+        );
+    }
+
+    let key_pem = key
+        .private_key_to_pem_pkcs8()
+        .map_err(|e| format!("Failed to encode signing key: {}", e))?;
+    let cert_pem = cert
+        .to_pem()
+        .map_err(|e| format!("Failed to encode certificate: {}", e))?;
+    write_key(&dir.join("key.pem"), &key_pem)
+        .map_err(|e| format!("Failed to save signing key: {}", e))?;
+    std::fs::write(dir.join("cert.pem"), cert_pem)
+        .map_err(|e| format!("Failed to save certificate: {}", e))?;
+    info!(
+        "Imported signing identity {} for {}",
+        info.serial_number, apple_id
+    );
+    Ok(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Must stay where isideload's CertificateIdentity::new looks, or imported identities are
+    // silently ignored
+    #[test]
+    fn identity_dir_matches_isideload() {
+        assert_eq!(
+            identity_dir(Path::new("/data"), "user@example.com"),
+            Path::new("/data/keys/63a710569261a24b3766275b7000ce8d7b32e2f7")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn key_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!("iloader-key-{}.pem", std::process::id()));
+        std::fs::write(&path, b"old").unwrap();
+        write_key(&path, b"key").unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        let contents = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(contents, b"key");
+    }
+}
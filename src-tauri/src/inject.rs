@@ -0,0 +1,666 @@
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use flate2::read::GzDecoder;
+use log::{debug, info, warn};
+use serde::Serialize;
+use xz2::{read::XzDecoder, stream::Stream};
+
+use crate::{
+    files::walk_local,
+    ipa::{macho_slices, plist_string, read_u32},
+};
+
+const LC_SEGMENT: u32 = 0x1;
+const LC_LOAD_DYLIB: u32 = 0xc;
+const LC_LOAD_WEAK_DYLIB: u32 = 0x18;
+const LC_SEGMENT_64: u32 = 0x19;
+const LC_ENCRYPTION_INFO: u32 = 0x21;
+const LC_ENCRYPTION_INFO_64: u32 = 0x2c;
+const LC_RPATH: u32 = 0x8000_001c;
+const LC_REEXPORT_DYLIB: u32 = 0x8000_001f;
+const LC_LOAD_UPWARD_DYLIB: u32 = 0x8000_0023;
+
+// Where the bundled substitute loader's @rpath reference resolves to
+const FRAMEWORKS_RPATH: &str = "@executable_path/Frameworks";
+
+// Where tweaks expect a substitute loader on a jailbroken device
+const SUBSTRATE_PATHS: &[&str] = &[
+    "/Library/Frameworks/CydiaSubstrate.framework/CydiaSubstrate",
+    "/var/jb/Library/Frameworks/CydiaSubstrate.framework/CydiaSubstrate",
+    "/usr/lib/libsubstrate.dylib",
+    "/var/jb/usr/lib/libsubstrate.dylib",
+];
+const BUNDLED_SUBSTRATE_PATH: &str = "@rpath/CydiaSubstrate.framework/CydiaSubstrate";
+
+// Directories of a .deb whose dylibs and frameworks are injected, relative to the package root
+// with or without the rootless "var/jb" prefix
+const DEB_DYLIB_DIRS: &[&str] = &[
+    "Library/MobileSubstrate/DynamicLibraries",
+    "usr/lib/TweakInject",
+];
+const DEB_FRAMEWORK_DIR: &str = "Library/Frameworks";
+
+#[derive(Clone, Default, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InjectionReport {
+    // Files copied into the app's Frameworks folder
+    pub copied: Vec<String>,
+    // Load commands added to the main executable
+    pub load_commands: Vec<String>,
+    // Dependencies redirected to the bundled substitute loader, as "<file>: <old path>"
+    pub rewritten: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+fn write_u32(bytes: &mut [u8], offset: usize, value: u32) {
+    bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn header_size(slice: &[u8]) -> Option<usize> {
+    match read_u32(slice, 0, true)? {
+        0xcffa_edfe => Some(32),
+        0xcefa_edfe => Some(28),
+        _ => None,
+    }
+}
+
+// (offset, cmd, cmdsize) of every load command in a thin Mach-O
+fn load_commands(slice: &[u8]) -> Vec<(usize, u32, usize)> {
+    let Some(header_size) = header_size(slice) else {
+        return vec![];
+    };
+    let ncmds = read_u32(slice, 16, false).unwrap_or(0);
+    let mut commands = vec![];
+    let mut offset = header_size;
+    for _ in 0..ncmds {
+        let (Some(cmd), Some(cmd_size)) = (
+            read_u32(slice, offset, false),
+            read_u32(slice, offset + 4, false),
+        ) else {
+            break;
+        };
+        if cmd_size == 0 {
+            break;
+        }
+        commands.push((offset, cmd, cmd_size as usize));
+        offset += cmd_size as usize;
+    }
+    commands
+}
+
+fn is_dylib_command(cmd: u32) -> bool {
+    matches!(
+        cmd,
+        LC_LOAD_DYLIB | LC_LOAD_WEAK_DYLIB | LC_REEXPORT_DYLIB | LC_LOAD_UPWARD_DYLIB
+    )
+}
+
+fn dylib_name(slice: &[u8], offset: usize, cmd_size: usize) -> Option<String> {
+    let name_offset = read_u32(slice, offset + 8, false)? as usize;
+    let bytes = slice.get(offset + name_offset..offset + cmd_size)?;
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    Some(String::from_utf8_lossy(&bytes[..end]).to_string())
+}
+
+pub fn linked_dylibs(slice: &[u8]) -> Vec<String> {
+    load_commands(slice)
+        .into_iter()
+        .filter(|(_, cmd, _)| is_dylib_command(*cmd))
+        .filter_map(|(offset, _, size)| dylib_name(slice, offset, size))
+        .collect()
+}
+
+// Load commands have to fit between the header and the first section's data
+fn load_command_space(slice: &[u8]) -> Option<usize> {
+    let mut first_data = slice.len();
+    for (offset, cmd, _) in load_commands(slice) {
+        let (nsects_at, header, section_size, section_offset_at) = match cmd {
+            LC_SEGMENT_64 => (64, 72, 80, 48),
+            LC_SEGMENT => (48, 56, 68, 40),
+            _ => continue,
+        };
+        let nsects = read_u32(slice, offset + nsects_at, false)? as usize;
+        for i in 0..nsects {
+            let section = offset + header + i * section_size;
+            let data = read_u32(slice, section + section_offset_at, false)? as usize;
+            if data != 0 {
+                first_data = first_data.min(data);
+            }
+        }
+    }
+    Some(first_data)
+}
+
+fn rpaths(slice: &[u8]) -> Vec<String> {
+    load_commands(slice)
+        .into_iter()
+        .filter(|(_, cmd, _)| *cmd == LC_RPATH)
+        .filter_map(|(offset, _, size)| dylib_name(slice, offset, size))
+        .collect()
+}
+
+// Whether a thin Mach-O is still FairPlay encrypted, which means it was copied off a device
+// without being decrypted and can't be patched or re-signed
+fn is_encrypted(slice: &[u8]) -> bool {
+    load_commands(slice).into_iter().any(|(offset, cmd, _)| {
+        matches!(cmd, LC_ENCRYPTION_INFO | LC_ENCRYPTION_INFO_64)
+            && read_u32(slice, offset + 16, false).is_some_and(|cryptid| cryptid != 0)
+    })
+}
+
+// Appends a load command made of `fields` followed by `path` to a thin Mach-O
+fn append_load_command(slice: &mut [u8], fields: &[u32], path: &str) -> Result<(), String> {
+    let header_size = header_size(slice).ok_or("Not a Mach-O binary".to_string())?;
+    let ncmds = read_u32(slice, 16, false).ok_or("Invalid Mach-O header".to_string())?;
+    let size_of_cmds = read_u32(slice, 20, false).ok_or("Invalid Mach-O header".to_string())?;
+
+    let path_offset = 4 * fields.len();
+    let cmd_size = (path_offset + path.len() + 1).div_ceil(8) * 8;
+    let start = header_size + size_of_cmds as usize;
+    let space = load_command_space(slice).ok_or("Invalid Mach-O segments".to_string())?;
+    if start + cmd_size > space {
+        return Err(format!(
+            "Not enough room in the executable to add a load command for {}",
+            path
+        ));
+    }
+    if slice[start..start + cmd_size].iter().any(|b| *b != 0) {
+        return Err("The space after the load commands is in use".to_string());
+    }
+
+    for (i, field) in fields.iter().enumerate() {
+        write_u32(slice, start + 4 * i, *field);
+    }
+    write_u32(slice, start + 4, cmd_size as u32);
+    slice[start + path_offset..start + path_offset + path.len()].copy_from_slice(path.as_bytes());
+    write_u32(slice, 16, ncmds + 1);
+    write_u32(slice, 20, size_of_cmds + cmd_size as u32);
+    Ok(())
+}
+
+// Adds an LC_LOAD_DYLIB for `path` to a thin Mach-O. Returns false if it was already linked.
+fn add_load_dylib(slice: &mut [u8], path: &str) -> Result<bool, String> {
+    if linked_dylibs(slice).iter().any(|p| p == path) {
+        return Ok(false);
+    }
+    // cmd, cmdsize (filled in), name offset, timestamp, current and compatibility version
+    append_load_command(
+        slice,
+        &[LC_LOAD_DYLIB, 0, 24, 2, 0x0001_0000, 0x0001_0000],
+        path,
+    )?;
+    Ok(true)
+}
+
+// Adds an LC_RPATH for `path` to a thin Mach-O. Returns false if it was already there.
+fn add_rpath(slice: &mut [u8], path: &str) -> Result<bool, String> {
+    if rpaths(slice).iter().any(|p| p == path) {
+        return Ok(false);
+    }
+    append_load_command(slice, &[LC_RPATH, 0, 12], path)?;
+    Ok(true)
+}
+
+// Points dependencies on a jailbreak's substitute loader at the bundled one. Returns the paths
+// that were rewritten and the ones that didn't fit in their load command.
+fn redirect_substrate(slice: &mut [u8]) -> (Vec<String>, Vec<String>) {
+    let mut rewritten = vec![];
+    let mut too_long = vec![];
+    for (offset, cmd, cmd_size) in load_commands(slice) {
+        if !is_dylib_command(cmd) {
+            continue;
+        }
+        let Some(name) = dylib_name(slice, offset, cmd_size) else {
+            continue;
+        };
+        if !SUBSTRATE_PATHS.contains(&name.as_str()) {
+            continue;
+        }
+        let Some(name_offset) = read_u32(slice, offset + 8, false).map(|o| o as usize) else {
+            continue;
+        };
+        if BUNDLED_SUBSTRATE_PATH.len() + 1 > cmd_size - name_offset {
+            too_long.push(name);
+            continue;
+        }
+        let field = &mut slice[offset + name_offset..offset + cmd_size];
+        field.fill(0);
+        field[..BUNDLED_SUBSTRATE_PATH.len()].copy_from_slice(BUNDLED_SUBSTRATE_PATH.as_bytes());
+        rewritten.push(name);
+    }
+    (rewritten, too_long)
+}
+
+// Applies `edit` to every slice of a (possibly fat) Mach-O file in place
+fn edit_macho<T>(
+    path: &Path,
+    mut edit: impl FnMut(&mut [u8]) -> Result<T, String>,
+) -> Result<Vec<T>, String> {
+    let mut data = std::fs::read(path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+    let slices = macho_slices(&data);
+    if slices.is_empty() {
+        return Err(format!("{:?} is not a Mach-O binary", path));
+    }
+    let mut results = vec![];
+    for (arch, range) in slices {
+        debug!("Editing {} slice of {:?}", arch, path);
+        results.push(edit(&mut data[range])?);
+    }
+    std::fs::write(path, data).map_err(|e| format!("Failed to write {:?}: {}", path, e))?;
+    Ok(results)
+}
+
+fn check_not_encrypted(binary: &Path) -> Result<(), String> {
+    let data = std::fs::read(binary).map_err(|e| format!("Failed to read {:?}: {}", binary, e))?;
+    if macho_slices(&data)
+        .into_iter()
+        .any(|(_, range)| is_encrypted(&data[range]))
+    {
+        return Err(format!(
+            "{:?} is encrypted. Decrypt the app before injecting into it.",
+            binary.file_name().unwrap_or_default()
+        ));
+    }
+    Ok(())
+}
+
+// Unpacks the data archive of a .deb into `dest`
+fn extract_deb(deb: &Path, dest: &Path) -> Result<(), String> {
+    let data = std::fs::read(deb).map_err(|e| format!("Failed to read {:?}: {}", deb, e))?;
+    if !data.starts_with(b"!<arch>\n") {
+        return Err(format!("{:?} is not a Debian package", deb));
+    }
+
+    let mut offset = 8;
+    while offset + 60 <= data.len() {
+        let header = &data[offset..offset + 60];
+        let name = String::from_utf8_lossy(&header[..16])
+            .trim_end()
+            .trim_end_matches('/')
+            .to_string();
+        let size: usize = String::from_utf8_lossy(&header[48..58])
+            .trim()
+            .parse()
+            .map_err(|_| format!("Invalid entry in {:?}", deb))?;
+        let body = data
+            .get(offset + 60..offset + 60 + size)
+            .ok_or(format!("{:?} is truncated", deb))?;
+
+        if let Some(compression) = name.strip_prefix("data.tar") {
+            let reader: Box<dyn Read + '_> = match compression {
+                "" => Box::new(body),
+                ".gz" => Box::new(GzDecoder::new(body)),
+                ".xz" => Box::new(XzDecoder::new(body)),
+                ".lzma" => Box::new(XzDecoder::new_stream(
+                    body,
+                    Stream::new_lzma_decoder(u64::MAX)
+                        .map_err(|e| format!("Failed to read {:?}: {}", deb, e))?,
+                )),
+                other => {
+                    return Err(format!(
+                        "Packages compressed with {} are not supported",
+                        other.trim_start_matches('.')
+                    ))
+                }
+            };
+            return tar::Archive::new(reader)
+                .unpack(dest)
+                .map_err(|e| format!("Failed to extract {:?}: {}", deb, e));
+        }
+
+        offset += 60 + size + size % 2;
+    }
+    Err(format!("{:?} does not contain any files", deb))
+}
+
+// Dylibs and frameworks a .deb installs, including rootless packages under var/jb
+fn deb_payloads(root: &Path) -> Vec<PathBuf> {
+    let mut payloads = vec![];
+    for prefix in ["", "var/jb/"] {
+        for dir in DEB_DYLIB_DIRS {
+            let Ok(entries) = std::fs::read_dir(root.join(format!("{}{}", prefix, dir))) else {
+                continue;
+            };
+            payloads.extend(
+                entries
+                    .flatten()
+                    .map(|e| e.path())
+                    .filter(|p| p.extension().is_some_and(|ext| ext == "dylib")),
+            );
+        }
+        let Ok(entries) = std::fs::read_dir(root.join(format!("{}{}", prefix, DEB_FRAMEWORK_DIR)))
+        else {
+            continue;
+        };
+        payloads.extend(entries.flatten().map(|e| e.path()).filter(|p| {
+            p.extension().is_some_and(|ext| ext == "framework")
+                && p.file_stem().is_some_and(|s| s != "CydiaSubstrate")
+        }));
+    }
+    payloads.sort();
+    payloads
+}
+
+fn framework_binary(framework: &Path) -> PathBuf {
+    let name = plist::from_file(framework.join("Info.plist"))
+        .ok()
+        .and_then(|info: plist::Dictionary| plist_string(&info, "CFBundleExecutable"))
+        .or_else(|| {
+            framework
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+        })
+        .unwrap_or_default();
+    framework.join(name)
+}
+
+fn copy_dir(src: &Path, dest: &Path) -> Result<(), String> {
+    let (dirs, files) = walk_local(src)?;
+    std::fs::create_dir_all(dest).map_err(|e| format!("Failed to copy {:?}: {}", src, e))?;
+    for dir in dirs {
+        std::fs::create_dir_all(dest.join(dir))
+            .map_err(|e| format!("Failed to copy {:?}: {}", src, e))?;
+    }
+    for (local, rel) in files {
+        std::fs::copy(&local, dest.join(rel))
+            .map_err(|e| format!("Failed to copy {:?}: {}", local, e))?;
+    }
+    Ok(())
+}
+
+// Redirects a copied binary's substitute loader dependency and records what happened
+fn redirect_in(binary: &Path, label: &str, report: &mut InjectionReport) -> Result<bool, String> {
+    let mut uses_substrate = false;
+    for (rewritten, too_long) in edit_macho(binary, |slice| Ok(redirect_substrate(slice)))? {
+        uses_substrate |= !rewritten.is_empty() || !too_long.is_empty();
+        for path in rewritten {
+            let entry = format!("{}: {}", label, path);
+            if !report.rewritten.contains(&entry) {
+                report.rewritten.push(entry);
+            }
+        }
+        for path in too_long {
+            let warning = format!(
+                "{} links {}, which can't be redirected to the bundled loader",
+                label, path
+            );
+            if !report.warnings.contains(&warning) {
+                report.warnings.push(warning);
+            }
+        }
+    }
+    Ok(uses_substrate)
+}
+
+// Copies dylibs, frameworks and the contents of .deb tweaks into the app and links them from its
+// main executable. `substrate` is a CydiaSubstrate.framework (or a compatible substitute) to
+// bundle for tweaks that hook with it.
+pub fn inject(
+    app_dir: &Path,
+    staging: &Path,
+    inject: &[String],
+    substrate: Option<&str>,
+) -> Result<InjectionReport, String> {
+    let mut report = InjectionReport::default();
+    let frameworks_dir = app_dir.join("Frameworks");
+    std::fs::create_dir_all(&frameworks_dir)
+        .map_err(|e| format!("Failed to create Frameworks folder: {}", e))?;
+
+    let mut payloads = vec![];
+    for (i, path) in inject.iter().enumerate() {
+        let path = PathBuf::from(path);
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("deb") => {
+                let dest = staging.join(format!("deb-{}", i));
+                extract_deb(&path, &dest)?;
+                let found = deb_payloads(&dest);
+                if found.is_empty() {
+                    report
+                        .warnings
+                        .push(format!("{:?} does not contain any dylibs", path));
+                }
+                payloads.extend(found);
+            }
+            Some("dylib") | Some("framework") => payloads.push(path),
+            _ => {
+                return Err(format!(
+                    "Can't inject {:?}, expected a .dylib, .framework or .deb",
+                    path
+                ))
+            }
+        }
+    }
+
+    let info: plist::Dictionary = plist::from_file(app_dir.join("Info.plist"))
+        .map_err(|e| format!("Failed to read Info.plist: {}", e))?;
+    let executable = app_dir.join(
+        plist_string(&info, "CFBundleExecutable")
+            .ok_or("Info.plist has no CFBundleExecutable".to_string())?,
+    );
+    check_not_encrypted(&executable)?;
+
+    let mut needs_substrate = false;
+    for payload in payloads {
+        let name = payload
+            .file_name()
+            .ok_or(format!("Invalid path {:?}", payload))?
+            .to_string_lossy()
+            .to_string();
+        let dest = frameworks_dir.join(&name);
+        if dest.exists() {
+            return Err(format!(
+                "The app's Frameworks folder already contains {}",
+                name
+            ));
+        }
+        let (binary, load_path) = if payload.is_dir() {
+            copy_dir(&payload, &dest)?;
+            let binary = framework_binary(&dest);
+            let binary_name = binary
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default();
+            (
+                binary,
+                format!("@executable_path/Frameworks/{}/{}", name, binary_name),
+            )
+        } else {
+            std::fs::copy(&payload, &dest)
+                .map_err(|e| format!("Failed to copy {:?}: {}", payload, e))?;
+            (dest, format!("@executable_path/Frameworks/{}", name))
+        };
+        check_not_encrypted(&binary)?;
+        info!("Injecting {} into {:?}", name, executable);
+        report.copied.push(name.clone());
+
+        needs_substrate |= redirect_in(&binary, &name, &mut report)?;
+        if edit_macho(&executable, |slice| add_load_dylib(slice, &load_path))?
+            .into_iter()
+            .any(|added| added)
+        {
+            report.load_commands.push(load_path);
+        }
+    }
+
+    match substrate {
+        Some(substrate) => {
+            if !substrate.ends_with(".framework") || !Path::new(substrate).is_dir() {
+                return Err(format!(
+                    "{} is not a framework, choose a CydiaSubstrate.framework",
+                    substrate
+                ));
+            }
+            let dest = frameworks_dir.join("CydiaSubstrate.framework");
+            if dest.exists() {
+                return Err(
+                    "The app's Frameworks folder already contains CydiaSubstrate.framework"
+                        .to_string(),
+                );
+            }
+            copy_dir(Path::new(substrate), &dest)?;
+            report.copied.push("CydiaSubstrate.framework".to_string());
+        }
+        None if needs_substrate => {
+            warn!("Injected tweaks depend on a substitute loader, but none was given");
+            report.warnings.push(
+                "The injected tweaks need CydiaSubstrate or a substitute for it. Choose one to bundle or they will fail to load.".to_string(),
+            );
+        }
+        None => {}
+    }
+
+    if !report.copied.is_empty()
+        && edit_macho(&executable, |slice| add_rpath(slice, FRAMEWORKS_RPATH))?
+            .into_iter()
+            .any(|added| added)
+    {
+        report
+            .load_commands
+            .push(format!("LC_RPATH {}", FRAMEWORKS_RPATH));
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUBSTRATE: &str = "/Library/Frameworks/CydiaSubstrate.framework/CydiaSubstrate";
+
+    fn push_u32(out: &mut Vec<u8>, value: u32) {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+
+    // A thin arm64 executable with one __TEXT section starting at `first_data` and `commands`
+    // (cmd, everything after cmdsize) after its segment
+    fn thin_macho(first_data: u32, commands: &[(u32, Vec<u8>)]) -> Vec<u8> {
+        let mut cmds = Vec::new();
+        push_u32(&mut cmds, LC_SEGMENT_64);
+        push_u32(&mut cmds, 72 + 80);
+        cmds.extend_from_slice(&[0; 56]);
+        push_u32(&mut cmds, 1);
+        push_u32(&mut cmds, 0);
+        let mut section = vec![0; 80];
+        section[48..52].copy_from_slice(&first_data.to_le_bytes());
+        cmds.extend_from_slice(&section);
+        for (cmd, body) in commands {
+            push_u32(&mut cmds, *cmd);
+            push_u32(&mut cmds, 8 + body.len() as u32);
+            cmds.extend_from_slice(body);
+        }
+
+        let mut out = Vec::new();
+        push_u32(&mut out, 0xfeed_facf);
+        push_u32(&mut out, 0x0100_000c);
+        push_u32(&mut out, 0);
+        push_u32(&mut out, 2);
+        push_u32(&mut out, 1 + commands.len() as u32);
+        push_u32(&mut out, cmds.len() as u32);
+        push_u32(&mut out, 0);
+        push_u32(&mut out, 0);
+        out.extend_from_slice(&cmds);
+        out.resize(first_data as usize + 0x100, 0);
+        out
+    }
+
+    fn encryption_info(cryptid: u32) -> (u32, Vec<u8>) {
+        let mut body = Vec::new();
+        push_u32(&mut body, 0x4000);
+        push_u32(&mut body, 0x1000);
+        push_u32(&mut body, cryptid);
+        push_u32(&mut body, 0);
+        (LC_ENCRYPTION_INFO_64, body)
+    }
+
+    #[test]
+    fn adds_load_dylib_once() {
+        let mut slice = thin_macho(0x400, &[]);
+        let path = "@executable_path/Frameworks/Tweak.dylib";
+        assert!(add_load_dylib(&mut slice, path).unwrap());
+        assert!(!add_load_dylib(&mut slice, path).unwrap());
+        assert_eq!(linked_dylibs(&slice), vec![path.to_string()]);
+        assert_eq!(read_u32(&slice, 16, false), Some(2));
+    }
+
+    #[test]
+    fn refuses_load_dylib_without_room() {
+        let mut slice = thin_macho(32 + 152 + 16, &[]);
+        let before = slice.clone();
+        assert!(add_load_dylib(&mut slice, "@executable_path/Frameworks/Tweak.dylib").is_err());
+        assert_eq!(slice, before);
+    }
+
+    #[test]
+    fn adds_rpath_once() {
+        let mut slice = thin_macho(0x400, &[]);
+        assert!(add_rpath(&mut slice, FRAMEWORKS_RPATH).unwrap());
+        assert!(!add_rpath(&mut slice, FRAMEWORKS_RPATH).unwrap());
+        assert_eq!(rpaths(&slice), vec![FRAMEWORKS_RPATH.to_string()]);
+        assert!(linked_dylibs(&slice).is_empty());
+    }
+
+    #[test]
+    fn redirects_substrate() {
+        let mut slice = thin_macho(0x400, &[]);
+        add_load_dylib(&mut slice, SUBSTRATE).unwrap();
+        add_load_dylib(&mut slice, "/usr/lib/libsubstrate.dylib").unwrap();
+        let (rewritten, too_long) = redirect_substrate(&mut slice);
+        assert_eq!(rewritten, vec![SUBSTRATE.to_string()]);
+        // Its load command only has room for a path as long as the original
+        assert_eq!(too_long, vec!["/usr/lib/libsubstrate.dylib".to_string()]);
+        assert_eq!(
+            linked_dylibs(&slice),
+            vec![
+                BUNDLED_SUBSTRATE_PATH.to_string(),
+                "/usr/lib/libsubstrate.dylib".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn detects_encryption() {
+        assert!(is_encrypted(&thin_macho(0x400, &[encryption_info(1)])));
+        assert!(!is_encrypted(&thin_macho(0x400, &[encryption_info(0)])));
+        assert!(!is_encrypted(&thin_macho(0x400, &[])));
+    }
+
+    #[test]
+    fn edits_every_fat_slice() {
+        let slice = thin_macho(0x400, &[]);
+        let mut fat = Vec::new();
+        fat.extend_from_slice(&0xcafe_babe_u32.to_be_bytes());
+        fat.extend_from_slice(&2u32.to_be_bytes());
+        for (i, subtype) in [0u32, 2].into_iter().enumerate() {
+            for field in [
+                0x0100_000c,
+                subtype,
+                0x1000 * (i as u32 + 1),
+                slice.len() as u32,
+                12,
+            ] {
+                fat.extend_from_slice(&field.to_be_bytes());
+            }
+        }
+        for i in 0..2 {
+            fat.resize(0x1000 * (i + 1), 0);
+            fat.extend_from_slice(&slice);
+        }
+
+        let path = std::env::temp_dir().join(format!("iloader-inject-{}", std::process::id()));
+        std::fs::write(&path, &fat).unwrap();
+        let added = edit_macho(&path, |slice| add_rpath(slice, FRAMEWORKS_RPATH));
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(added.unwrap(), vec![true, true]);
+        let slices = macho_slices(&data);
+        assert_eq!(slices.len(), 2);
+        for (_, range) in slices {
+            assert_eq!(rpaths(&data[range]), vec![FRAMEWORKS_RPATH.to_string()]);
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use std::path::Path;
+
+use idevice::{afc::AfcClient, installation_proxy::InstallationProxyClient, IdeviceService};
+use log::{debug, info};
+use serde_json::json;
+use tauri::{State, Window};
+use tokio::sync::mpsc::unbounded_channel;
+
+use crate::{
+    device::{get_provider, DeviceInfo, DeviceInfoMutex},
+    files::write_file_with_progress,
+    operation::Operation,
+};
+
+const STAGING_DIR: &str = "/PublicStaging";
+
+// Installer errors that have a clearer explanation than the code installd reports
+const INSTALL_ERRORS: &[(&str, &str)] = &[
+    (
+        "ApplicationVerificationFailed",
+        "The app's signature is not valid for this device. Ad-hoc builds must include this device's UDID in their provisioning profile.",
+    ),
+    (
+        "MismatchedApplicationIdentifierEntitlement",
+        "An app with the same bundle ID is already installed with a different signing team. Uninstall it first.",
+    ),
+    (
+        "DeviceOSVersionTooLow",
+        "The app requires a newer version of iOS than this device runs.",
+    ),
+    (
+        "PackageInspectionFailed",
+        "The IPA could not be read by the device. It may be damaged or not an iOS app.",
+    ),
+    (
+        "APIInternalError",
+        "The installer on the device failed. Try restarting the device.",
+    ),
+];
+
+fn describe_install_error(error: String) -> String {
+    match INSTALL_ERRORS.iter().find(|(code, _)| error.contains(code)) {
+        Some((code, hint)) => format!("{} ({})\n\nDetails: {}", hint, code, error),
+        None => error,
+    }
+}
+
+pub async fn upload_package(
+    device: &DeviceInfo,
+    app_path: &Path,
+    on_progress: impl FnMut(u64),
+) -> Result<String, String> {
+    let provider = get_provider(device).await?;
+    let mut afc_client = AfcClient::connect(&provider)
+        .await
+        .map_err(|e| format!("Failed to connect to AFC: {}", e))?;
+
+    if let Err(e) = afc_client.mk_dir(STAGING_DIR).await {
+        debug!("Could not create {}: {}", STAGING_DIR, e);
+    }
+    let remote_path = format!("{}/iloader.ipa", STAGING_DIR);
+    let contents =
+        std::fs::read(app_path).map_err(|e| format!("Failed to read {:?}: {}", app_path, e))?;
+    info!(
+        "Uploading {:?} ({} bytes) to {}",
+        app_path,
+        contents.len(),
+        remote_path
+    );
+    write_file_with_progress(&mut afc_client, &remote_path, &contents, on_progress).await?;
+    Ok(remote_path)
+}
+
+// Installs an IPA as-is, keeping its existing signature
+pub async fn install_package(
+    device: &DeviceInfo,
+    remote_path: &str,
+    on_progress: impl Fn(u64) + Clone,
+) -> Result<(), String> {
+    let provider = get_provider(device).await?;
+    let mut installation_proxy = InstallationProxyClient::connect(&provider)
+        .await
+        .map_err(|e| format!("Failed to connect to installation proxy: {}", e))?;
+
+    installation_proxy
+        .install_with_callback(
+            remote_path,
+            None,
+            |(percent, on_progress)| async move { on_progress(percent) },
+            on_progress,
+        )
+        .await
+        .map_err(|e| describe_install_error(format!("Failed to install app: {}", e)))
+}
+
+#[tauri::command]
+pub async fn install_ipa_operation(
+    window: Window,
+    run_id: Option<u64>,
+    device_state: State<'_, DeviceInfoMutex>,
+    app_path: String,
+) -> Result<(), String> {
+    let op = Operation::new("install_ipa".to_string(), &window, run_id);
+    op.set_params(json!({ "appPath": app_path }));
+    op.start("upload")?;
+    let device = {
+        let device_guard = device_state.lock().unwrap();
+        match &*device_guard {
+            Some(d) => d.clone(),
+            None => return op.fail("upload", "No device selected".to_string()),
+        }
+    };
+    op.wait_for("upload", Some(&device.uuid), None).await?;
+    let remote_path = op.fail_if_err(
+        "upload",
+        upload_package(&device, Path::new(&app_path), |percent| {
+            let _ = op.progress("upload", percent);
+        })
+        .await,
+    )?;
+    op.move_on("upload", "install")?;
+
+    let (tx, rx) = unbounded_channel();
+    op.fail_if_err(
+        "install",
+        op.track(
+            "install",
+            rx,
+            install_package(&device, &remote_path, move |percent| {
+                let _ = tx.send(percent);
+            }),
+        )
+        .await,
+    )?;
+    info!("Installed {} on {}", app_path, device.name);
+    op.complete("install")?;
+    Ok(())
+}
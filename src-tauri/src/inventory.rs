@@ -0,0 +1,147 @@
+use std::{
+    cmp::Reverse,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::info;
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+use tauri_plugin_store::StoreExt;
+
+use crate::presets::EntitlementOverrides;
+
+// Used by expiring_soon when the caller doesn't pass a window
+const DEFAULT_EXPIRY_WINDOW_HOURS: u64 = 48;
+
+// Held while the inventory is read and written back, so installs finishing together on different
+// devices don't drop each other's entries
+static INVENTORY_LOCK: Mutex<()> = Mutex::new(());
+
+// An app iloader installed on a device. There is at most one entry per device and bundle ID.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InventoryEntry {
+    pub udid: String,
+    pub device_name: String,
+    pub bundle_id: String,
+    pub original_bundle_id: String,
+    pub name: String,
+    pub version: Option<String>,
+    pub build: Option<String>,
+    pub apple_id: String,
+    pub team_id: String,
+    pub team_name: Option<String>,
+    pub certificate_serial: Option<String>,
+    pub profile_expiry: Option<u64>,
+    pub installed_at: u64,
+    pub source: String,
+    // Original bundle IDs of the extensions stripped before signing, so a refresh can strip the
+    // same ones again
+    #[serde(default)]
+    pub removed_extensions: Vec<String>,
+    // Files injected before signing and the bundled substitute loader, reapplied on refresh
+    #[serde(default)]
+    pub injected: Vec<String>,
+    #[serde(default)]
+    pub substrate: Option<String>,
+    // Icon, entitlement overrides and quota choice of the original install, also reapplied on
+    // refresh so it installs the same app
+    #[serde(default)]
+    pub icon: Option<String>,
+    #[serde(default)]
+    pub entitlements: EntitlementOverrides,
+    #[serde(default)]
+    pub ignore_app_id_quota: bool,
+}
+
+pub fn load_inventory(handle: &AppHandle) -> Result<Vec<InventoryEntry>, String> {
+    let store = handle
+        .store("inventory.json")
+        .map_err(|e| format!("Failed to get store: {:?}", e))?;
+    Ok(store
+        .get("apps")
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default())
+}
+
+fn save_inventory(handle: &AppHandle, entries: &[InventoryEntry]) -> Result<(), String> {
+    let store = handle
+        .store("inventory.json")
+        .map_err(|e| format!("Failed to get store: {:?}", e))?;
+    store.set(
+        "apps",
+        serde_json::to_value(entries).map_err(|e| e.to_string())?,
+    );
+    Ok(())
+}
+
+pub fn record_install(handle: &AppHandle, entry: InventoryEntry) -> Result<(), String> {
+    let _lock = INVENTORY_LOCK.lock().unwrap();
+    let mut entries = load_inventory(handle)?;
+    entries.retain(|e| !(e.udid == entry.udid && e.bundle_id == entry.bundle_id));
+    info!(
+        "Recording {} on {} in inventory",
+        entry.bundle_id, entry.device_name
+    );
+    entries.push(entry);
+    save_inventory(handle, &entries)
+}
+
+pub fn remove_install(handle: &AppHandle, udid: &str, bundle_id: &str) -> Result<(), String> {
+    let _lock = INVENTORY_LOCK.lock().unwrap();
+    let mut entries = load_inventory(handle)?;
+    entries.retain(|e| !(e.udid == udid && e.bundle_id == bundle_id));
+    save_inventory(handle, &entries)
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+// Latest profile expiry that still counts as within `hours` from now. The hours come from the
+// frontend, so a huge value saturates instead of overflowing.
+pub fn expiry_cutoff(hours: u64) -> u64 {
+    now().saturating_add(hours.saturating_mul(60 * 60))
+}
+
+#[tauri::command]
+pub fn list_installed_inventory(
+    handle: AppHandle,
+    udid: Option<String>,
+) -> Result<Vec<InventoryEntry>, String> {
+    let mut entries = load_inventory(&handle)?;
+    if let Some(udid) = udid {
+        entries.retain(|e| e.udid == udid);
+    }
+    entries.sort_by_key(|e| Reverse(e.installed_at));
+    Ok(entries)
+}
+
+// Apps whose profile expires within `within_hours`, including ones that already expired,
+// soonest first. Apps without a known expiry are left out.
+#[tauri::command]
+pub fn expiring_soon(
+    handle: AppHandle,
+    within_hours: Option<u64>,
+) -> Result<Vec<InventoryEntry>, String> {
+    let cutoff = expiry_cutoff(within_hours.unwrap_or(DEFAULT_EXPIRY_WINDOW_HOURS));
+    let mut entries: Vec<InventoryEntry> = load_inventory(&handle)?
+        .into_iter()
+        .filter(|e| e.profile_expiry.is_some_and(|expiry| expiry <= cutoff))
+        .collect();
+    entries.sort_by_key(|e| e.profile_expiry);
+    Ok(entries)
+}
+
+#[tauri::command]
+pub fn remove_inventory_entry(
+    handle: AppHandle,
+    udid: String,
+    bundle_id: String,
+) -> Result<(), String> {
+    remove_install(&handle, &udid, &bundle_id)
+}
@@ -0,0 +1,1010 @@
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use flate2::{read::DeflateDecoder, write::ZlibEncoder, Compression};
+use isideload::developer_session::DeveloperDeviceType;
+use log::{debug, warn};
+use openssl::{
+    pkcs7::{Pkcs7, Pkcs7Flags},
+    stack::Stack,
+    x509::X509,
+};
+use serde::Serialize;
+use tauri::AppHandle;
+use zip::ZipArchive;
+
+use crate::{
+    account::{get_account, get_developer_session},
+    files::walk_local,
+    prepare::{installed_bundle_id, resolve_modifications},
+};
+
+const CPU_TYPE_X86: u32 = 7;
+const CPU_TYPE_ARM: u32 = 12;
+const CPU_ARCH_ABI64: u32 = 0x0100_0000;
+const LC_CODE_SIGNATURE: u32 = 0x1d;
+const CSMAGIC_EMBEDDED_SIGNATURE: u32 = 0xfade_0cc0;
+const CSMAGIC_EMBEDDED_ENTITLEMENTS: u32 = 0xfade_7171;
+const CSMAGIC_BLOBWRAPPER: u32 = 0xfade_0b01;
+const CSSLOT_SIGNATURESLOT: u32 = 0x10000;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleExtensionInfo {
+    pub name: String,
+    pub bundle_id: String,
+    pub path: String,
+    pub kind: String,
+    pub entitlements: Option<plist::Dictionary>,
+    pub app_groups: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IpaReport {
+    pub bundle_id: String,
+    pub display_name: String,
+    pub version: Option<String>,
+    pub build: Option<String>,
+    pub minimum_os_version: Option<String>,
+    pub device_families: Vec<String>,
+    pub architectures: Vec<String>,
+    pub entitlements: Option<plist::Dictionary>,
+    pub app_groups: Vec<String>,
+    pub extensions: Vec<BundleExtensionInfo>,
+    pub icon_png: Option<String>,
+    pub app_ids_required: usize,
+    pub app_ids_to_register: Option<usize>,
+    pub app_ids_available: Option<u64>,
+}
+
+// An app bundle that is either still inside an IPA or already extracted to a .app directory.
+// Paths are always relative to the .app.
+pub enum AppBundle {
+    Ipa {
+        archive: ZipArchive<File>,
+        root: String,
+    },
+    Dir(PathBuf),
+}
+
+fn is_main_info_plist(name: &str) -> bool {
+    let parts: Vec<&str> = name.split('/').collect();
+    parts.len() == 3
+        && parts[0] == "Payload"
+        && parts[1].ends_with(".app")
+        && parts[2] == "Info.plist"
+}
+
+impl AppBundle {
+    pub fn open(app_path: &Path) -> Result<AppBundle, String> {
+        if app_path.is_dir() {
+            return Ok(AppBundle::Dir(app_path.to_path_buf()));
+        }
+
+        let file = File::open(app_path).map_err(|e| format!("Failed to open IPA: {}", e))?;
+        let archive = ZipArchive::new(file).map_err(|e| format!("Failed to read IPA: {}", e))?;
+        let root = archive
+            .file_names()
+            .find(|n| is_main_info_plist(n))
+            .map(|n| n.trim_end_matches("Info.plist").to_string())
+            .ok_or("IPA does not contain an app bundle".to_string())?;
+
+        Ok(AppBundle::Ipa { archive, root })
+    }
+
+    pub fn files(&self) -> Result<Vec<String>, String> {
+        match self {
+            AppBundle::Ipa { archive, root } => Ok(archive
+                .file_names()
+                .filter_map(|n| n.strip_prefix(root.as_str()))
+                .filter(|n| !n.is_empty() && !n.ends_with('/'))
+                .map(|n| n.to_string())
+                .collect()),
+            AppBundle::Dir(dir) => Ok(walk_local(dir)?.1.into_iter().map(|(_, rel)| rel).collect()),
+        }
+    }
+
+    pub fn read(&mut self, rel: &str) -> Result<Vec<u8>, String> {
+        match self {
+            AppBundle::Ipa { archive, root } => {
+                let mut entry = archive
+                    .by_name(&format!("{}{}", root, rel))
+                    .map_err(|e| format!("Failed to read {}: {}", rel, e))?;
+                let mut bytes = Vec::new();
+                entry
+                    .read_to_end(&mut bytes)
+                    .map_err(|e| format!("Failed to read {}: {}", rel, e))?;
+                Ok(bytes)
+            }
+            AppBundle::Dir(dir) => {
+                std::fs::read(dir.join(rel)).map_err(|e| format!("Failed to read {}: {}", rel, e))
+            }
+        }
+    }
+
+    pub fn read_plist(&mut self, rel: &str) -> Result<plist::Dictionary, String> {
+        plist::from_bytes(&self.read(rel)?).map_err(|e| format!("Failed to parse {}: {}", rel, e))
+    }
+
+    // Directories of every app extension and watch app, relative to the .app. Nested bundles
+    // (a watch app's own extensions) come after their parent.
+    pub fn nested_bundles(&self) -> Result<Vec<String>, String> {
+        let mut dirs: Vec<String> = self
+            .files()?
+            .into_iter()
+            .filter_map(|f| f.strip_suffix("/Info.plist").map(|d| d.to_string()))
+            .filter(|d| {
+                !d.starts_with("Frameworks/")
+                    && (d.ends_with(".appex") || (d.starts_with("Watch/") && d.ends_with(".app")))
+            })
+            .collect();
+        dirs.sort();
+        Ok(dirs)
+    }
+}
+
+pub fn read_info_plist(app_path: &Path) -> Result<plist::Dictionary, String> {
+    AppBundle::open(app_path)?.read_plist("Info.plist")
+}
+
+pub fn read_bundle_id(app_path: &Path) -> Result<String, String> {
+    plist_string(&read_info_plist(app_path)?, "CFBundleIdentifier")
+        .ok_or("Info.plist is missing CFBundleIdentifier".to_string())
+}
+
+pub fn plist_string(dict: &plist::Dictionary, key: &str) -> Option<String> {
+    dict.get(key)
+        .and_then(|v| v.as_string())
+        .map(|s| s.to_string())
+}
+
+pub fn read_u32(bytes: &[u8], offset: usize, big_endian: bool) -> Option<u32> {
+    let b: [u8; 4] = bytes.get(offset..offset + 4)?.try_into().ok()?;
+    Some(if big_endian {
+        u32::from_be_bytes(b)
+    } else {
+        u32::from_le_bytes(b)
+    })
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    let b: [u8; 8] = bytes.get(offset..offset + 8)?.try_into().ok()?;
+    Some(u64::from_be_bytes(b))
+}
+
+fn arch_name(cpu_type: u32, cpu_subtype: u32) -> String {
+    match (cpu_type, cpu_subtype & 0x00ff_ffff) {
+        (t, 2) if t == CPU_TYPE_ARM | CPU_ARCH_ABI64 => "arm64e".to_string(),
+        (t, _) if t == CPU_TYPE_ARM | CPU_ARCH_ABI64 => "arm64".to_string(),
+        (CPU_TYPE_ARM, 9) => "armv7".to_string(),
+        (CPU_TYPE_ARM, 11) => "armv7s".to_string(),
+        (CPU_TYPE_ARM, _) => "arm".to_string(),
+        (t, _) if t == CPU_TYPE_X86 | CPU_ARCH_ABI64 => "x86_64".to_string(),
+        (CPU_TYPE_X86, _) => "i386".to_string(),
+        (t, s) => format!("unknown ({}/{})", t, s),
+    }
+}
+
+// Splits a (possibly fat) Mach-O into its slices as (architecture, byte range).
+pub fn macho_slices(binary: &[u8]) -> Vec<(String, std::ops::Range<usize>)> {
+    let magic = read_u32(binary, 0, true).unwrap_or(0);
+    if magic == 0xcafe_babe || magic == 0xcafe_babf {
+        let is_64 = magic == 0xcafe_babf;
+        let count = read_u32(binary, 4, true).unwrap_or(0) as usize;
+        let entry_size = if is_64 { 32 } else { 20 };
+        return (0..count)
+            .filter_map(|i| {
+                let base = 8 + i * entry_size;
+                let cpu_type = read_u32(binary, base, true)?;
+                let cpu_subtype = read_u32(binary, base + 4, true)?;
+                let (offset, size) = if is_64 {
+                    (
+                        read_u64(binary, base + 8)? as usize,
+                        read_u64(binary, base + 16)? as usize,
+                    )
+                } else {
+                    (
+                        read_u32(binary, base + 8, true)? as usize,
+                        read_u32(binary, base + 12, true)? as usize,
+                    )
+                };
+                let end = offset.checked_add(size)?;
+                binary.get(offset..end)?;
+                Some((arch_name(cpu_type, cpu_subtype), offset..end))
+            })
+            .collect();
+    }
+
+    match (read_u32(binary, 4, false), read_u32(binary, 8, false)) {
+        (Some(cpu_type), Some(cpu_subtype)) if magic == 0xcffa_edfe || magic == 0xcefa_edfe => {
+            vec![(arch_name(cpu_type, cpu_subtype), 0..binary.len())]
+        }
+        _ => vec![],
+    }
+}
+
+// Returns the offset and size of the code signature of a thin Mach-O, if it is signed.
+fn code_signature_range(slice: &[u8]) -> Option<std::ops::Range<usize>> {
+    let header_size = match read_u32(slice, 0, true)? {
+        0xcffa_edfe => 32,
+        0xcefa_edfe => 28,
+        _ => return None,
+    };
+    let ncmds = read_u32(slice, 16, false)?;
+
+    let mut offset = header_size;
+    for _ in 0..ncmds {
+        let cmd = read_u32(slice, offset, false)?;
+        let cmd_size = read_u32(slice, offset + 4, false)? as usize;
+        if cmd == LC_CODE_SIGNATURE {
+            let data_off = read_u32(slice, offset + 8, false)? as usize;
+            let data_size = read_u32(slice, offset + 12, false)? as usize;
+            let end = data_off.checked_add(data_size)?;
+            slice.get(data_off..end)?;
+            return Some(data_off..end);
+        }
+        if cmd_size == 0 {
+            return None;
+        }
+        offset = offset.checked_add(cmd_size)?;
+    }
+    None
+}
+
+// The blobs of the first slice's code signature superblob as (slot type, blob)
+fn signature_blobs(binary: &[u8]) -> Vec<(u32, &[u8])> {
+    let Some((_, range)) = macho_slices(binary).into_iter().next() else {
+        return vec![];
+    };
+    let slice = &binary[range];
+    let Some(signature) = code_signature_range(slice).map(|r| &slice[r]) else {
+        return vec![];
+    };
+    if read_u32(signature, 0, true) != Some(CSMAGIC_EMBEDDED_SIGNATURE) {
+        return vec![];
+    }
+
+    let count = read_u32(signature, 8, true).unwrap_or(0) as usize;
+    (0..count)
+        .filter_map(|i| {
+            let slot = read_u32(signature, 12 + i * 8, true)?;
+            let blob_offset = read_u32(signature, 12 + i * 8 + 4, true)? as usize;
+            let length = read_u32(signature, blob_offset + 4, true)? as usize;
+            Some((
+                slot,
+                signature.get(blob_offset..blob_offset.checked_add(length)?)?,
+            ))
+        })
+        .collect()
+}
+
+pub fn macho_entitlements(binary: &[u8]) -> Option<plist::Dictionary> {
+    signature_blobs(binary)
+        .into_iter()
+        .find(|(_, blob)| read_u32(blob, 0, true) == Some(CSMAGIC_EMBEDDED_ENTITLEMENTS))
+        .and_then(|(_, blob)| plist::from_bytes(blob.get(8..)?).ok())
+}
+
+// Serial of the certificate that signed the binary, read from the CMS blob of its code
+// signature. Unlike the profile's certificate list this is the one certificate that has to stay
+// valid for the app to launch.
+pub fn macho_signer_serial(binary: &[u8]) -> Option<String> {
+    let (_, blob) = signature_blobs(binary)
+        .into_iter()
+        .find(|(slot, _)| *slot == CSSLOT_SIGNATURESLOT)?;
+    if read_u32(blob, 0, true)? != CSMAGIC_BLOBWRAPPER {
+        return None;
+    }
+    let cms = Pkcs7::from_der(blob.get(8..)?).ok()?;
+    let certs = Stack::new().ok()?;
+    let signers = cms.signers(&certs, Pkcs7Flags::empty()).ok()?;
+    let signer = signers.iter().next()?;
+    certificate_serial(&signer.to_der().ok()?)
+}
+
+// The signer serial of the bundle's main executable
+pub fn signer_serial(app_path: &Path) -> Result<Option<String>, String> {
+    let mut bundle = AppBundle::open(app_path)?;
+    let info = bundle.read_plist("Info.plist")?;
+    let executable =
+        executable_path("", &info).ok_or("Info.plist is missing CFBundleExecutable".to_string())?;
+    Ok(macho_signer_serial(&bundle.read(&executable)?))
+}
+
+fn app_groups(entitlements: &Option<plist::Dictionary>) -> Vec<String> {
+    entitlements
+        .as_ref()
+        .and_then(|e| e.get("com.apple.security.application-groups"))
+        .and_then(|v| v.as_array())
+        .map(|groups| {
+            groups
+                .iter()
+                .filter_map(|g| g.as_string().map(|s| s.to_string()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn device_family_name(family: u64) -> String {
+    match family {
+        1 => "iPhone".to_string(),
+        2 => "iPad".to_string(),
+        3 => "Apple TV".to_string(),
+        4 => "Apple Watch".to_string(),
+        6 => "Mac".to_string(),
+        7 => "Apple Vision".to_string(),
+        n => format!("Unknown ({})", n),
+    }
+}
+
+// Xcode stores PNGs in Apple's "CgBI" variant (BGRA, premultiplied, raw deflate stream) that
+// nothing outside of iOS can display. This rewrites one into a regular PNG; normal PNGs are
+// returned unchanged.
+pub fn normalize_png(png: &[u8]) -> Result<Vec<u8>, String> {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    if !png.starts_with(SIGNATURE) {
+        return Err("Icon is not a PNG".to_string());
+    }
+
+    let mut chunks = Vec::new();
+    let mut offset = SIGNATURE.len();
+    while let Some(length) = read_u32(png, offset, true) {
+        let length = length as usize;
+        let kind = png
+            .get(offset + 4..offset + 8)
+            .ok_or("Truncated PNG".to_string())?;
+        let end = (offset + 8)
+            .checked_add(length)
+            .ok_or("Truncated PNG".to_string())?;
+        let data = png
+            .get(offset + 8..end)
+            .ok_or("Truncated PNG".to_string())?;
+        chunks.push((kind, data));
+        offset = end + 4;
+    }
+
+    if chunks.first().map(|(kind, _)| *kind) != Some(b"CgBI".as_slice()) {
+        return Ok(png.to_vec());
+    }
+
+    let ihdr = chunks
+        .iter()
+        .find(|(kind, _)| *kind == b"IHDR")
+        .map(|(_, data)| *data)
+        .ok_or("PNG is missing IHDR".to_string())?;
+    let width = read_u32(ihdr, 0, true).ok_or("Invalid IHDR".to_string())? as usize;
+
+    let compressed: Vec<u8> = chunks
+        .iter()
+        .filter(|(kind, _)| *kind == b"IDAT")
+        .flat_map(|(_, data)| data.iter().copied())
+        .collect();
+    let mut raw = Vec::new();
+    DeflateDecoder::new(compressed.as_slice())
+        .read_to_end(&mut raw)
+        .map_err(|e| format!("Failed to inflate icon: {}", e))?;
+
+    // Each scanline is a filter byte followed by BGRA pixels. Filters only ever combine a byte
+    // with the same channel of a neighbouring pixel, so the channels can be swapped in place
+    // without unfiltering.
+    let stride = 1 + width * 4;
+    for row in raw.chunks_mut(stride) {
+        for pixel in row[1..].chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+        }
+    }
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(&raw)
+        .map_err(|e| format!("Failed to compress icon: {}", e))?;
+    let idat = encoder
+        .finish()
+        .map_err(|e| format!("Failed to compress icon: {}", e))?;
+
+    let mut out = SIGNATURE.to_vec();
+    for (kind, data) in [
+        (b"IHDR".as_slice(), ihdr),
+        (b"IDAT".as_slice(), idat.as_slice()),
+        (b"IEND".as_slice(), &[][..]),
+    ] {
+        out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        out.extend_from_slice(kind);
+        out.extend_from_slice(data);
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(kind);
+        hasher.update(data);
+        out.extend_from_slice(&hasher.finalize().to_be_bytes());
+    }
+    Ok(out)
+}
+
+fn icon_names(info: &plist::Dictionary) -> Vec<String> {
+    let mut names = Vec::new();
+    let primary = info
+        .get("CFBundleIcons")
+        .and_then(|v| v.as_dictionary())
+        .and_then(|d| d.get("CFBundlePrimaryIcon"))
+        .and_then(|v| v.as_dictionary());
+    for files in [
+        primary.and_then(|p| p.get("CFBundleIconFiles")),
+        info.get("CFBundleIconFiles"),
+    ]
+    .into_iter()
+    .flatten()
+    .filter_map(|v| v.as_array())
+    {
+        names.extend(
+            files
+                .iter()
+                .filter_map(|f| f.as_string().map(|s| s.to_string())),
+        );
+    }
+    if let Some(name) = plist_string(info, "CFBundleIconFile") {
+        names.push(name);
+    }
+    names
+}
+
+// Picks the largest PNG at the top level of the bundle matching one of the declared icon names.
+fn extract_icon(bundle: &mut AppBundle, info: &plist::Dictionary) -> Option<Vec<u8>> {
+    let names = icon_names(info);
+    let candidates: Vec<String> = bundle
+        .files()
+        .ok()?
+        .into_iter()
+        .filter(|f| {
+            !f.contains('/')
+                && f.ends_with(".png")
+                && names
+                    .iter()
+                    .any(|n| f.starts_with(n.trim_end_matches(".png")))
+        })
+        .collect();
+
+    let mut best: Option<Vec<u8>> = None;
+    for candidate in candidates {
+        if let Ok(bytes) = bundle.read(&candidate) {
+            if best.as_ref().is_none_or(|b| bytes.len() > b.len()) {
+                best = Some(bytes);
+            }
+        }
+    }
+
+    match normalize_png(&best?) {
+        Ok(png) => Some(png),
+        Err(e) => {
+            warn!("Failed to convert app icon: {}", e);
+            None
+        }
+    }
+}
+
+fn executable_path(dir: &str, info: &plist::Dictionary) -> Option<String> {
+    let executable = plist_string(info, "CFBundleExecutable")?;
+    Some(if dir.is_empty() {
+        executable
+    } else {
+        format!("{}/{}", dir, executable)
+    })
+}
+
+pub fn inspect(app_path: &Path) -> Result<IpaReport, String> {
+    let mut bundle = AppBundle::open(app_path)?;
+    let info = bundle.read_plist("Info.plist")?;
+
+    let bundle_id = plist_string(&info, "CFBundleIdentifier")
+        .ok_or("Info.plist is missing CFBundleIdentifier".to_string())?;
+    let display_name = plist_string(&info, "CFBundleDisplayName")
+        .or_else(|| plist_string(&info, "CFBundleName"))
+        .unwrap_or_else(|| bundle_id.clone());
+
+    let (architectures, entitlements) = match executable_path("", &info) {
+        Some(path) => {
+            let binary = bundle.read(&path)?;
+            (
+                macho_slices(&binary)
+                    .into_iter()
+                    .map(|(arch, _)| arch)
+                    .collect(),
+                macho_entitlements(&binary),
+            )
+        }
+        None => (vec![], None),
+    };
+
+    let mut extensions = Vec::new();
+    for dir in bundle.nested_bundles()? {
+        let ext_info = match bundle.read_plist(&format!("{}/Info.plist", dir)) {
+            Ok(i) => i,
+            Err(e) => {
+                warn!("Skipping nested bundle {}: {}", dir, e);
+                continue;
+            }
+        };
+        let ext_entitlements = executable_path(&dir, &ext_info)
+            .and_then(|path| bundle.read(&path).ok())
+            .and_then(|binary| macho_entitlements(&binary));
+        extensions.push(BundleExtensionInfo {
+            name: dir.rsplit('/').next().unwrap_or_default().to_string(),
+            bundle_id: plist_string(&ext_info, "CFBundleIdentifier").unwrap_or_default(),
+            kind: if dir.ends_with(".appex") {
+                "extension".to_string()
+            } else {
+                "watch".to_string()
+            },
+            app_groups: app_groups(&ext_entitlements),
+            entitlements: ext_entitlements,
+            path: dir,
+        });
+    }
+
+    let icon_png = extract_icon(&mut bundle, &info).map(|png| STANDARD.encode(png));
+
+    Ok(IpaReport {
+        display_name,
+        version: plist_string(&info, "CFBundleShortVersionString"),
+        build: plist_string(&info, "CFBundleVersion"),
+        minimum_os_version: plist_string(&info, "MinimumOSVersion"),
+        device_families: info
+            .get("UIDeviceFamily")
+            .and_then(|v| v.as_array())
+            .map(|families| {
+                families
+                    .iter()
+                    .filter_map(|f| f.as_unsigned_integer())
+                    .map(device_family_name)
+                    .collect()
+            })
+            .unwrap_or_default(),
+        architectures,
+        app_groups: app_groups(&entitlements),
+        entitlements,
+        app_ids_required: 1 + extensions.len(),
+        extensions,
+        icon_png,
+        app_ids_to_register: None,
+        app_ids_available: None,
+        bundle_id,
+    })
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileInfo {
+    pub name: Option<String>,
+    pub uuid: Option<String>,
+    pub application_identifier: Option<String>,
+    pub team_id: Option<String>,
+    pub team_name: Option<String>,
+    pub expiration: Option<u64>,
+    pub certificate_serials: Vec<String>,
+    pub entitlements: Option<plist::Dictionary>,
+    // UDIDs the profile can be installed on, empty for enterprise profiles
+    pub provisioned_devices: Vec<String>,
+    pub provisions_all_devices: bool,
+}
+
+fn date_secs(value: Option<&plist::Value>) -> Option<u64> {
+    let date = value?.as_date()?;
+    SystemTime::from(date)
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs())
+}
+
+fn certificate_serial(der: &[u8]) -> Option<String> {
+    let cert = X509::from_der(der).ok()?;
+    let serial = cert.serial_number().to_bn().ok()?.to_hex_str().ok()?;
+    Some(serial.to_string())
+}
+
+// Provisioning profiles are CMS signed plists. The signature isn't checked, the XML payload is
+// just cut out of the envelope.
+pub fn parse_provisioning_profile(data: &[u8]) -> Option<ProfileInfo> {
+    let start = data.windows(5).position(|w| w == b"<?xml")?;
+    let end = data.windows(8).rposition(|w| w == b"</plist>")? + 8;
+    let profile: plist::Dictionary = plist::from_bytes(data.get(start..end)?).ok()?;
+
+    let entitlements = profile.get("Entitlements").and_then(|v| v.as_dictionary());
+    Some(ProfileInfo {
+        name: plist_string(&profile, "Name"),
+        uuid: plist_string(&profile, "UUID"),
+        application_identifier: entitlements
+            .and_then(|e| plist_string(e, "application-identifier")),
+        team_id: profile
+            .get("TeamIdentifier")
+            .and_then(|v| v.as_array())
+            .and_then(|ids| ids.first())
+            .and_then(|id| id.as_string())
+            .map(|id| id.to_string()),
+        team_name: plist_string(&profile, "TeamName"),
+        expiration: date_secs(profile.get("ExpirationDate")),
+        certificate_serials: profile
+            .get("DeveloperCertificates")
+            .and_then(|v| v.as_array())
+            .map(|certs| {
+                certs
+                    .iter()
+                    .filter_map(|c| c.as_data())
+                    .filter_map(certificate_serial)
+                    .collect()
+            })
+            .unwrap_or_default(),
+        entitlements: entitlements.cloned(),
+        provisioned_devices: profile
+            .get("ProvisionedDevices")
+            .and_then(|v| v.as_array())
+            .map(|udids| {
+                udids
+                    .iter()
+                    .filter_map(|u| u.as_string().map(|u| u.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default(),
+        provisions_all_devices: profile
+            .get("ProvisionsAllDevices")
+            .and_then(|v| v.as_boolean())
+            .unwrap_or(false),
+    })
+}
+
+// The main bundle ID followed by the IDs of every extension and watch app
+pub fn bundle_ids(app_path: &Path) -> Result<(String, Vec<String>), String> {
+    let mut bundle = AppBundle::open(app_path)?;
+    let bundle_id = plist_string(&bundle.read_plist("Info.plist")?, "CFBundleIdentifier")
+        .ok_or("Info.plist is missing CFBundleIdentifier".to_string())?;
+
+    let mut extension_ids = Vec::new();
+    for dir in bundle.nested_bundles()? {
+        if let Some(id) = bundle
+            .read_plist(&format!("{}/Info.plist", dir))
+            .ok()
+            .and_then(|info| plist_string(&info, "CFBundleIdentifier"))
+        {
+            extension_ids.push(id);
+        }
+    }
+    Ok((bundle_id, extension_ids))
+}
+
+// The App ID identifiers signing registers for this app when it is installed as `main_id`.
+// Extensions are renamed the way register_app_ids renames them, by swapping the prefix.
+pub fn predicted_app_ids(bundle_id: &str, extension_ids: &[String], main_id: &str) -> Vec<String> {
+    let mut ids = vec![main_id.to_string()];
+    ids.extend(
+        extension_ids
+            .iter()
+            .map(|ext| match ext.strip_prefix(bundle_id) {
+                Some(suffix) => format!("{}{}", main_id, suffix),
+                None => ext.clone(),
+            }),
+    );
+    ids
+}
+
+#[tauri::command]
+pub async fn inspect_ipa(handle: AppHandle, app_path: String) -> Result<IpaReport, String> {
+    let mut report = inspect(Path::new(&app_path))?;
+    if get_account().is_err() {
+        return Ok(report);
+    }
+    let prediction = async {
+        // The overrides saved for this IPA are used again unless the user changes them
+        let modifications = resolve_modifications(&handle, &app_path, None, None, None)?;
+        let dev_session = get_developer_session().await?;
+        let team = dev_session
+            .get_team()
+            .await
+            .map_err(|e| format!("Failed to get developer team: {:?}", e))?;
+        let app_ids = dev_session
+            .list_app_ids(DeveloperDeviceType::Ios, &team)
+            .await
+            .map_err(|e| format!("Failed to list App IDs: {:?}", e))?;
+        let extension_ids: Vec<String> = report
+            .extensions
+            .iter()
+            .map(|e| e.bundle_id.clone())
+            .collect();
+        let main_id = installed_bundle_id(
+            modifications
+                .bundle_id
+                .as_ref()
+                .unwrap_or(&report.bundle_id),
+            &team.team_id,
+            modifications.exact_bundle_id,
+        );
+        let to_register = predicted_app_ids(&report.bundle_id, &extension_ids, &main_id)
+            .iter()
+            .filter(|id| !app_ids.app_ids.iter().any(|a| &a.identifier == *id))
+            .count();
+        Ok::<_, String>((to_register, app_ids.available_quantity))
+    };
+
+    match prediction.await {
+        Ok((to_register, available)) => {
+            debug!(
+                "{} needs {} new App IDs, {:?} available",
+                report.bundle_id, to_register, available
+            );
+            report.app_ids_to_register = Some(to_register);
+            report.app_ids_available = available;
+        }
+        Err(e) => warn!("Could not predict App ID usage: {}", e),
+    }
+
+    Ok(report)
+}
+
+// A self-signed development certificate and its key, shared by the tests that need a signer
+#[cfg(test)]
+pub(crate) mod test_support {
+    use openssl::{
+        asn1::Asn1Time,
+        bn::BigNum,
+        hash::MessageDigest,
+        pkey::{PKey, Private},
+        rsa::Rsa,
+        x509::{X509NameBuilder, X509},
+    };
+
+    pub fn certificate(serial: u32) -> (X509, PKey<Private>) {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", "Apple Development: test")
+            .unwrap();
+        let name = name.build();
+        let mut builder = X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        builder
+            .set_serial_number(&BigNum::from_u32(serial).unwrap().to_asn1_integer().unwrap())
+            .unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(&name).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(7).unwrap())
+            .unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+        (builder.build(), key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{test_support::certificate, *};
+
+    fn blob(magic: u32, payload: &[u8]) -> Vec<u8> {
+        let mut out = magic.to_be_bytes().to_vec();
+        out.extend_from_slice(&(8 + payload.len() as u32).to_be_bytes());
+        out.extend_from_slice(payload);
+        out
+    }
+
+    // A thin arm64 Mach-O whose only load command is LC_CODE_SIGNATURE
+    fn thin_macho(blobs: &[(u32, Vec<u8>)]) -> Vec<u8> {
+        let mut signature = Vec::new();
+        let mut index = Vec::new();
+        let mut blob_offset = 12 + blobs.len() * 8;
+        for (slot, data) in blobs {
+            index.extend_from_slice(&slot.to_be_bytes());
+            index.extend_from_slice(&(blob_offset as u32).to_be_bytes());
+            blob_offset += data.len();
+        }
+        signature.extend_from_slice(&CSMAGIC_EMBEDDED_SIGNATURE.to_be_bytes());
+        signature.extend_from_slice(&(blob_offset as u32).to_be_bytes());
+        signature.extend_from_slice(&(blobs.len() as u32).to_be_bytes());
+        signature.extend_from_slice(&index);
+        for (_, data) in blobs {
+            signature.extend_from_slice(data);
+        }
+
+        let mut binary = Vec::new();
+        for value in [
+            0xfeed_facf,
+            CPU_TYPE_ARM | CPU_ARCH_ABI64,
+            0,
+            2,
+            1,
+            16,
+            0,
+            0,
+        ] {
+            binary.extend_from_slice(&u32::to_le_bytes(value));
+        }
+        for value in [LC_CODE_SIGNATURE, 16, 48, signature.len() as u32] {
+            binary.extend_from_slice(&u32::to_le_bytes(value));
+        }
+        binary.extend_from_slice(&signature);
+        binary
+    }
+
+    #[test]
+    fn reads_thin_macho_entitlements() {
+        let xml = br#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0"><dict><key>get-task-allow</key><true/></dict></plist>"#;
+        let binary = thin_macho(&[(5, blob(CSMAGIC_EMBEDDED_ENTITLEMENTS, xml))]);
+
+        let slices = macho_slices(&binary);
+        assert_eq!(slices, vec![("arm64".to_string(), 0..binary.len())]);
+        let entitlements = macho_entitlements(&binary).unwrap();
+        assert_eq!(
+            entitlements
+                .get("get-task-allow")
+                .and_then(|v| v.as_boolean()),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn reads_signer_serial() {
+        let (cert, key) = certificate(0x1abc);
+        let certs = Stack::new().unwrap();
+        let cms = Pkcs7::sign(
+            &cert,
+            &key,
+            &certs,
+            b"",
+            Pkcs7Flags::DETACHED | Pkcs7Flags::BINARY,
+        )
+        .unwrap();
+        let binary = thin_macho(&[(
+            CSSLOT_SIGNATURESLOT,
+            blob(CSMAGIC_BLOBWRAPPER, &cms.to_der().unwrap()),
+        )]);
+
+        assert_eq!(macho_signer_serial(&binary), Some("1ABC".to_string()));
+        assert!(macho_entitlements(&binary).is_none());
+    }
+
+    #[test]
+    fn rejects_out_of_range_fat_slices() {
+        let mut binary = Vec::new();
+        binary.extend_from_slice(&0xcafe_babf_u32.to_be_bytes());
+        binary.extend_from_slice(&2u32.to_be_bytes());
+        // An arm64 slice whose end overflows and one past the end of the file
+        for (offset, size) in [(u64::MAX - 1, 16u64), (64, 4096)] {
+            binary.extend_from_slice(&(CPU_TYPE_ARM | CPU_ARCH_ABI64).to_be_bytes());
+            binary.extend_from_slice(&0u32.to_be_bytes());
+            binary.extend_from_slice(&offset.to_be_bytes());
+            binary.extend_from_slice(&size.to_be_bytes());
+            binary.extend_from_slice(&[0; 8]);
+        }
+        binary.resize(128, 0);
+
+        assert!(macho_slices(&binary).is_empty());
+        assert!(macho_entitlements(&binary).is_none());
+    }
+
+    #[test]
+    fn rejects_overflowing_code_signature() {
+        let mut binary = thin_macho(&[]);
+        // dataoff + datasize past usize::MAX on 32-bit and past the end everywhere
+        binary[40..44].copy_from_slice(&u32::MAX.to_le_bytes());
+        binary[44..48].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(code_signature_range(&binary).is_none());
+    }
+
+    fn chunk(kind: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut out = (data.len() as u32).to_be_bytes().to_vec();
+        out.extend_from_slice(kind);
+        out.extend_from_slice(data);
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(kind);
+        hasher.update(data);
+        out.extend_from_slice(&hasher.finalize().to_be_bytes());
+        out
+    }
+
+    #[test]
+    fn converts_cgbi_png() {
+        // One 2x1 scanline, filter byte followed by BGRA pixels
+        let raw = [0u8, 1, 2, 3, 255, 10, 20, 30, 255];
+        let mut deflate = flate2::write::DeflateEncoder::new(Vec::new(), Compression::default());
+        deflate.write_all(&raw).unwrap();
+        let mut ihdr = Vec::new();
+        ihdr.extend_from_slice(&2u32.to_be_bytes());
+        ihdr.extend_from_slice(&1u32.to_be_bytes());
+        ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png.extend(chunk(b"CgBI", &[0x50, 0, 0x20, 6]));
+        png.extend(chunk(b"IHDR", &ihdr));
+        png.extend(chunk(b"IDAT", &deflate.finish().unwrap()));
+        png.extend(chunk(b"IEND", &[]));
+
+        let normal = normalize_png(&png).unwrap();
+        assert_eq!(normalize_png(&normal).unwrap(), normal);
+        assert!(normal.windows(4).all(|w| w != b"CgBI"));
+        let idat_start = normal.windows(4).position(|w| w == b"IDAT").unwrap() + 4;
+        let length = read_u32(&normal, idat_start - 8, true).unwrap() as usize;
+        let mut pixels = Vec::new();
+        flate2::read::ZlibDecoder::new(&normal[idat_start..idat_start + length])
+            .read_to_end(&mut pixels)
+            .unwrap();
+        assert_eq!(pixels, [0, 3, 2, 1, 255, 30, 20, 10, 255]);
+    }
+
+    #[test]
+    fn rejects_truncated_png() {
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png.extend_from_slice(&u32::MAX.to_be_bytes());
+        png.extend_from_slice(b"IHDR");
+        assert!(normalize_png(&png).is_err());
+        assert!(normalize_png(b"GIF89a").is_err());
+    }
+
+    #[test]
+    fn parses_provisioning_profile() {
+        let (cert, _) = certificate(0x1abc);
+        let mut entitlements = plist::Dictionary::new();
+        entitlements.insert(
+            "application-identifier".into(),
+            "TEAM123.com.example.app.TEAM123".into(),
+        );
+        let mut profile = plist::Dictionary::new();
+        profile.insert("Name".into(), "iOS Team Provisioning Profile".into());
+        profile.insert(
+            "TeamIdentifier".into(),
+            vec![plist::Value::from("TEAM123")].into(),
+        );
+        profile.insert("TeamName".into(), "Test Team".into());
+        profile.insert(
+            "ExpirationDate".into(),
+            plist::Date::from(UNIX_EPOCH + std::time::Duration::from_secs(1_800_000_000)).into(),
+        );
+        profile.insert(
+            "DeveloperCertificates".into(),
+            vec![plist::Value::Data(cert.to_der().unwrap())].into(),
+        );
+        profile.insert(
+            "ProvisionedDevices".into(),
+            vec![plist::Value::from("UDID")].into(),
+        );
+        profile.insert("Entitlements".into(), entitlements.into());
+
+        // The plist sits inside a CMS envelope
+        let mut data = vec![0x30, 0x80, 0x06, 0x09];
+        plist::to_writer_xml(&mut data, &profile).unwrap();
+        data.extend_from_slice(&[0xa0, 0x82, 0x00, 0x00]);
+
+        let info = parse_provisioning_profile(&data).unwrap();
+        assert_eq!(info.name.as_deref(), Some("iOS Team Provisioning Profile"));
+        assert_eq!(
+            info.application_identifier.as_deref(),
+            Some("TEAM123.com.example.app.TEAM123")
+        );
+        assert_eq!(info.team_id.as_deref(), Some("TEAM123"));
+        assert_eq!(info.team_name.as_deref(), Some("Test Team"));
+        assert_eq!(info.expiration, Some(1_800_000_000));
+        assert_eq!(info.certificate_serials, vec!["1ABC".to_string()]);
+        assert_eq!(info.provisioned_devices, vec!["UDID".to_string()]);
+        assert!(!info.provisions_all_devices);
+        assert!(parse_provisioning_profile(b"not a profile").is_none());
+    }
+
+    #[test]
+    fn predicts_extension_app_ids_from_the_prefix() {
+        let extensions = vec![
+            "com.foo.app.widget".to_string(),
+            "com.foo.app.com.foo.app".to_string(),
+        ];
+        assert_eq!(
+            predicted_app_ids("com.foo.app", &extensions, "com.foo.app.TEAM"),
+            [
+                "com.foo.app.TEAM",
+                "com.foo.app.TEAM.widget",
+                "com.foo.app.TEAM.com.foo.app",
+            ]
+        );
+    }
+}
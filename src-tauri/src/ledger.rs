@@ -0,0 +1,202 @@
+use std::sync::Mutex;
+
+use isideload::developer_session::{DeveloperDeviceType, ListAppIdsResponse};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+use tauri_plugin_store::StoreExt;
+
+use crate::{
+    account::{get_account, get_developer_session},
+    inventory::now,
+};
+
+// Free accounts can register a limited number of App IDs per rolling week. Deleting an App ID
+// does not give its slot back, so Apple's own list can't tell when the next one frees up.
+const QUOTA_WINDOW_SECS: u64 = 7 * 24 * 60 * 60;
+
+// Held while a team's ledger is read and written back, so signings finishing together don't
+// drop each other's App IDs
+static LEDGER_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LedgerEntry {
+    pub identifier: String,
+    pub created_at: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppIdQuota {
+    // None when Apple reports no limit, as it does for paid teams
+    pub max_quantity: Option<u64>,
+    pub available_quantity: Option<u64>,
+    pub registered_in_window: usize,
+    pub free_now: Option<u64>,
+    pub next_slot_at: Option<u64>,
+    pub entries: Vec<LedgerEntry>,
+}
+
+fn ledger_key(apple_id: &str, team_id: &str) -> String {
+    format!("{}/{}", apple_id.to_lowercase(), team_id)
+}
+
+pub fn load_entries(
+    handle: &AppHandle,
+    apple_id: &str,
+    team_id: &str,
+) -> Result<Vec<LedgerEntry>, String> {
+    let store = handle
+        .store("app_ids.json")
+        .map_err(|e| format!("Failed to get store: {:?}", e))?;
+    Ok(store
+        .get(ledger_key(apple_id, team_id))
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default())
+}
+
+pub fn record(
+    handle: &AppHandle,
+    apple_id: &str,
+    team_id: &str,
+    identifiers: &[String],
+) -> Result<(), String> {
+    let _lock = LEDGER_LOCK.lock().unwrap();
+    let mut entries = load_entries(handle, apple_id, team_id)?;
+    let cutoff = now().saturating_sub(QUOTA_WINDOW_SECS);
+    entries.retain(|e| e.created_at >= cutoff);
+    entries.extend(identifiers.iter().map(|identifier| LedgerEntry {
+        identifier: identifier.clone(),
+        created_at: now(),
+    }));
+
+    let store = handle
+        .store("app_ids.json")
+        .map_err(|e| format!("Failed to get store: {:?}", e))?;
+    store.set(
+        ledger_key(apple_id, team_id),
+        serde_json::to_value(&entries).map_err(|e| e.to_string())?,
+    );
+    info!("Recorded {} new App IDs for {}", identifiers.len(), team_id);
+    Ok(())
+}
+
+pub fn compute_quota(entries: Vec<LedgerEntry>, app_ids: &ListAppIdsResponse) -> AppIdQuota {
+    let cutoff = now().saturating_sub(QUOTA_WINDOW_SECS);
+    let mut entries: Vec<LedgerEntry> = entries
+        .into_iter()
+        .filter(|e| e.created_at >= cutoff)
+        .collect();
+    entries.sort_by_key(|e| e.created_at);
+
+    let ledger_free = app_ids
+        .max_quantity
+        .map(|max| max.saturating_sub(entries.len() as u64));
+    let free_now = match (app_ids.available_quantity, ledger_free) {
+        (Some(available), Some(ledger_free)) => Some(available.min(ledger_free)),
+        (available, ledger_free) => available.or(ledger_free),
+    };
+    AppIdQuota {
+        max_quantity: app_ids.max_quantity,
+        available_quantity: app_ids.available_quantity,
+        registered_in_window: entries.len(),
+        free_now,
+        next_slot_at: entries.first().map(|e| e.created_at + QUOTA_WINDOW_SECS),
+        entries,
+    }
+}
+
+fn describe_wait(until: u64) -> String {
+    let secs = until.saturating_sub(now());
+    if secs >= 24 * 60 * 60 {
+        format!("{} days", secs.div_ceil(24 * 60 * 60))
+    } else if secs >= 60 * 60 {
+        format!("{} hours", secs.div_ceil(60 * 60))
+    } else {
+        format!("{} minutes", secs.div_ceil(60).max(1))
+    }
+}
+
+// Checks whether registering `needed` App IDs fits in the quota. Returns the identifiers that
+// don't exist yet, or a user-facing explanation if they would exceed it.
+pub fn preflight(
+    handle: &AppHandle,
+    apple_id: &str,
+    team_id: &str,
+    needed: &[String],
+    app_ids: &ListAppIdsResponse,
+) -> Result<Vec<String>, String> {
+    let to_register: Vec<String> = needed
+        .iter()
+        .filter(|id| !app_ids.app_ids.iter().any(|a| &a.identifier == *id))
+        .cloned()
+        .collect();
+    let quota = compute_quota(load_entries(handle, apple_id, team_id)?, app_ids);
+
+    let Some(free_now) = quota.free_now else {
+        return Ok(to_register);
+    };
+    if to_register.len() as u64 <= free_now {
+        return Ok(to_register);
+    }
+
+    let wait = match quota.next_slot_at {
+        Some(at) => format!(
+            "The next App ID slot frees up in about {}.",
+            describe_wait(at)
+        ),
+        None => "Delete unused App IDs or wait for older ones to expire.".to_string(),
+    };
+    Err(format!(
+        "This app needs {} new App IDs, but only {} of {} are free right now. {}\n\n\
+        Removing app extensions before installing reduces the number of App IDs needed.",
+        to_register.len(),
+        free_now,
+        quota
+            .max_quantity
+            .map_or_else(|| "the limit".to_string(), |max| max.to_string()),
+        wait
+    ))
+}
+
+// Records every identifier in `after` that wasn't in `before`
+pub fn record_new(
+    handle: &AppHandle,
+    apple_id: &str,
+    team_id: &str,
+    before: &ListAppIdsResponse,
+    after: &ListAppIdsResponse,
+) {
+    let new: Vec<String> = after
+        .app_ids
+        .iter()
+        .filter(|a| !before.app_ids.iter().any(|b| b.identifier == a.identifier))
+        .map(|a| a.identifier.clone())
+        .collect();
+    if new.is_empty() {
+        return;
+    }
+    if let Err(e) = record(handle, apple_id, team_id, &new) {
+        warn!("Failed to record new App IDs: {}", e);
+    }
+}
+
+#[tauri::command]
+pub async fn app_id_quota(handle: AppHandle) -> Result<AppIdQuota, String> {
+    let account = get_account()?;
+    let dev_session = get_developer_session().await?;
+    let team = dev_session
+        .get_team()
+        .await
+        .map_err(|e| format!("Failed to get developer team: {:?}", e))?;
+    let app_ids = dev_session
+        .list_app_ids(DeveloperDeviceType::Ios, &team)
+        .await
+        .map_err(|e| format!("Failed to list App IDs: {:?}", e))?;
+
+    Ok(compute_quota(
+        load_entries(&handle, &account.apple_id, &team.team_id)?,
+        &app_ids,
+    ))
+}
@@ -6,7 +6,27 @@ mod device;
 mod sideload;
 #[macro_use]
 mod pairing;
+mod anisette;
+mod apps;
+mod backup;
+mod download;
+mod files;
+mod groups;
+mod history;
+mod identity;
+mod inject;
+mod install;
+mod inventory;
+mod ipa;
+mod ledger;
 mod operation;
+mod prepare;
+mod presets;
+mod profile_sign;
+mod refresh;
+mod sign;
+mod signing;
+mod sources;
 
 use crate::{
     account::{
@@ -14,9 +34,33 @@ use crate::{
         invalidate_account, list_app_ids, logged_in_as, login_email_pass, login_stored_pass,
         revoke_certificate,
     },
+    anisette::{
+        add_anisette_server, check_anisette_servers, list_anisette_servers, remove_anisette_server,
+    },
+    apps::{list_user_apps, uninstall_app_operation},
+    backup::{backup_app_data_operation, list_app_backups, restore_app_data_operation},
     device::{list_devices, set_selected_device, DeviceInfoMutex},
+    files::{
+        app_file_info, delete_app_file, download_app_file, list_app_files, mkdir_app_file,
+        rename_app_file, upload_app_file,
+    },
+    history::{clear_history, get_history_entry, list_history, mark_interrupted},
+    identity::{current_identity, export_identity, import_identity},
+    install::install_ipa_operation,
+    inventory::{expiring_soon, list_installed_inventory, remove_inventory_entry},
+    ipa::inspect_ipa,
+    ledger::app_id_quota,
+    operation::{cancel_operation, list_operations, reserve_run_id},
     pairing::{installed_pairing_apps, place_pairing_cmd},
+    presets::{delete_preset, export_presets, import_presets, list_presets, save_preset},
+    profile_sign::sign_with_profile_operation,
+    refresh::{get_refresh_settings, refresh_now, set_refresh_settings, start_scheduler},
     sideload::{install_sidestore_operation, sideload_operation},
+    sign::sign_ipa_operation,
+    sources::{
+        add_source, browse_source_apps, install_from_source_operation, list_sources,
+        refresh_sources, remove_source, search_source_apps, source_updates,
+    },
 };
 use tauri::Manager;
 use env_logger::Env;
@@ -38,6 +82,8 @@ pub fn run() {
         .plugin(tauri_plugin_store::Builder::new().build())
         .setup(|app| {
             app.manage(DeviceInfoMutex::new(None));
+            mark_interrupted(app.handle());
+            start_scheduler(app.handle().clone());
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
@@ -45,11 +91,24 @@ pub fn run() {
             invalidate_account,
             logged_in_as,
             login_stored_pass,
+            list_anisette_servers,
+            add_anisette_server,
+            remove_anisette_server,
+            check_anisette_servers,
             delete_account,
             list_devices,
             sideload_operation,
             set_selected_device,
             install_sidestore_operation,
+            cancel_operation,
+            reserve_run_id,
+            list_operations,
+            list_history,
+            get_history_entry,
+            clear_history,
+            current_identity,
+            export_identity,
+            import_identity,
             get_certificates,
             get_certificates_cached,
             revoke_certificate,
@@ -58,6 +117,42 @@ pub fn run() {
             cleanup_all,
             installed_pairing_apps,
             place_pairing_cmd,
+            list_app_files,
+            app_file_info,
+            upload_app_file,
+            download_app_file,
+            rename_app_file,
+            mkdir_app_file,
+            delete_app_file,
+            backup_app_data_operation,
+            restore_app_data_operation,
+            list_app_backups,
+            inspect_ipa,
+            app_id_quota,
+            list_installed_inventory,
+            expiring_soon,
+            remove_inventory_entry,
+            get_refresh_settings,
+            set_refresh_settings,
+            refresh_now,
+            sign_ipa_operation,
+            sign_with_profile_operation,
+            install_ipa_operation,
+            list_user_apps,
+            uninstall_app_operation,
+            list_sources,
+            add_source,
+            remove_source,
+            refresh_sources,
+            browse_source_apps,
+            search_source_apps,
+            source_updates,
+            install_from_source_operation,
+            list_presets,
+            save_preset,
+            delete_preset,
+            export_presets,
+            import_presets,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -1,63 +1,375 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, LazyLock, Mutex,
+    },
+    time::Duration,
+};
+
+use log::{info, warn};
 use serde::Serialize;
-use tauri::{Emitter, Window};
+use serde_json::Value;
+use tauri::{Emitter, Manager, Window};
+use tokio::sync::{mpsc::UnboundedReceiver, OwnedMutexGuard};
+
+use isideload::AppleAccount;
+
+use crate::{
+    account::get_account,
+    history::{record_run, redact, HistoryEntry},
+    inventory::now,
+};
+
+pub const CANCELLED: &str = "Operation cancelled";
+
+// Set by cancel_operation, checked by the operation between steps and by long loops inside them
+#[derive(Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OperationInfo {
+    pub run_id: u64,
+    pub id: String,
+    pub step: Option<String>,
+    // Percentage of the current step, for steps that report one
+    pub progress: Option<u64>,
+    pub udid: Option<String>,
+    pub apple_id: Option<String>,
+    // Waiting for another operation on the same device or account to finish
+    pub queued: bool,
+    pub started_at: u64,
+}
+
+struct RunningOperation {
+    token: CancelToken,
+    info: OperationInfo,
+}
+
+static NEXT_RUN_ID: AtomicU64 = AtomicU64::new(1);
+
+// Every operation that has been started and hasn't returned yet, by run ID
+static RUNNING: LazyLock<Mutex<HashMap<u64, RunningOperation>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+// One lock per device UDID and per Apple ID, held for the rest of the operation by wait_for
+static RESOURCE_LOCKS: LazyLock<Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+// Always in the same order so two operations can't each hold what the other needs
+fn resource_locks(
+    udid: Option<&str>,
+    apple_id: Option<&str>,
+) -> Vec<(String, Arc<tokio::sync::Mutex<()>>)> {
+    let mut locks = RESOURCE_LOCKS.lock().unwrap();
+    apple_id
+        .map(|a| format!("account:{}", a.to_lowercase()))
+        .into_iter()
+        .chain(udid.map(|u| format!("device:{}", u)))
+        .map(|key| {
+            let lock = locks.entry(key.clone()).or_default().clone();
+            (key, lock)
+        })
+        .collect()
+}
+
+// For commands that change a device or account outside of an operation: waits for any
+// operation using them and keeps them until the guards are dropped
+pub async fn lock_resources(
+    udid: Option<&str>,
+    apple_id: Option<&str>,
+) -> Vec<OwnedMutexGuard<()>> {
+    let mut guards = Vec::new();
+    for (_, lock) in resource_locks(udid, apple_id) {
+        guards.push(lock.lock_owned().await);
+    }
+    guards
+}
 
 pub struct Operation<'a> {
     id: String,
+    run_id: u64,
     window: &'a Window,
+    token: CancelToken,
+    locks: Mutex<Vec<OwnedMutexGuard<()>>>,
+    account: Mutex<Option<Arc<AppleAccount>>>,
+    history: Mutex<HistoryEntry>,
 }
 
 #[derive(Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct OperationUpdate<'a> {
+    run_id: u64,
     update_type: &'a str,
     step_id: &'a str,
     extra_details: Option<String>,
+    progress: Option<u64>,
 }
 
 impl<'a> Operation<'a> {
-    pub fn new(id: String, window: &'a Window) -> Operation<'a> {
-        Operation { id, window }
+    // `run_id` is the one the frontend reserved to tell this run's events apart, a new one is
+    // used if it is missing or taken
+    pub fn new(id: String, window: &'a Window, run_id: Option<u64>) -> Operation<'a> {
+        let token = CancelToken::default();
+        let mut running = RUNNING.lock().unwrap();
+        let run_id = run_id
+            .filter(|r| !running.contains_key(r))
+            .unwrap_or_else(|| NEXT_RUN_ID.fetch_add(1, Ordering::SeqCst));
+        running.insert(
+            run_id,
+            RunningOperation {
+                token: token.clone(),
+                info: OperationInfo {
+                    run_id,
+                    id: id.clone(),
+                    step: None,
+                    progress: None,
+                    udid: None,
+                    apple_id: None,
+                    queued: false,
+                    started_at: now(),
+                },
+            },
+        );
+        drop(running);
+        let history = HistoryEntry::new(&id, run_id);
+        record_run(window.app_handle(), &history);
+        let history = Mutex::new(history);
+        Operation {
+            id,
+            run_id,
+            window,
+            token,
+            locks: Mutex::new(vec![]),
+            account: Mutex::new(None),
+            history,
+        }
+    }
+
+    // Parameters saved with the run in the history, with anything secret redacted
+    pub fn set_params(&self, params: Value) {
+        self.update_history(|history| history.params = redact(params));
+    }
+
+    // A problem that didn't stop the operation, kept in the history
+    pub fn warn(&self, message: String) {
+        warn!("Operation {}: {}", self.id, message);
+        self.update_history(|history| history.warnings.push(message));
+    }
+
+    // Saves the run to the history on every change, so a run that never finishes is still there
+    fn update_history(&self, f: impl FnOnce(&mut HistoryEntry)) {
+        let mut history = self.history.lock().unwrap();
+        f(&mut history);
+        record_run(self.window.app_handle(), &history);
+    }
+
+    fn update_info(&self, f: impl FnOnce(&mut OperationInfo)) {
+        if let Some(running) = RUNNING.lock().unwrap().get_mut(&self.run_id) {
+            f(&mut running.info);
+        }
+    }
+
+    // Waits until no other operation is using the device or account, then keeps them for the
+    // rest of this operation. Operations on other devices and accounts still run in parallel.
+    pub async fn wait_for(
+        &self,
+        step: &str,
+        udid: Option<&str>,
+        apple_id: Option<&str>,
+    ) -> Result<(), String> {
+        self.update_info(|info| {
+            info.udid = udid.map(|u| u.to_string());
+            info.apple_id = apple_id.map(|a| a.to_string());
+        });
+        self.update_history(|history| {
+            history.udid = udid.map(|u| u.to_string());
+            history.apple_id = apple_id.map(|a| a.to_string());
+        });
+        for (key, lock) in resource_locks(udid, apple_id) {
+            let guard = match lock.clone().try_lock_owned() {
+                Ok(guard) => guard,
+                Err(_) => {
+                    info!("Operation {} is waiting for {}", self.id, key);
+                    self.update_info(|info| info.queued = true);
+                    let mut acquire = Box::pin(lock.lock_owned());
+                    loop {
+                        match tokio::time::timeout(Duration::from_millis(250), &mut acquire).await {
+                            Ok(guard) => break guard,
+                            Err(_) => self.check_cancelled(step)?,
+                        }
+                    }
+                }
+            };
+            self.locks.lock().unwrap().push(guard);
+        }
+        self.update_info(|info| info.queued = false);
+        self.check_cancelled(step)
+    }
+
+    // wait_for with the logged in account, which the operation keeps using even if another
+    // account logs in while it runs
+    pub async fn wait_for_account(&self, step: &str, udid: Option<&str>) -> Result<(), String> {
+        let account = get_account().ok();
+        self.wait_for(step, udid, account.as_ref().map(|a| a.apple_id.as_str()))
+            .await?;
+        *self.account.lock().unwrap() = account;
+        Ok(())
+    }
+
+    // The account locked by wait_for_account
+    pub fn account(&self) -> Result<Arc<AppleAccount>, String> {
+        self.account
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or("Not logged in".to_string())
+    }
+
+    pub fn token(&self) -> &CancelToken {
+        &self.token
     }
 
     pub fn move_on(&self, old_id: &str, new_id: &str) -> Result<(), String> {
         self.complete(old_id)?;
+        self.check_cancelled(new_id)?;
         self.start(new_id)
     }
 
+    // Stops the operation at `id` if it was cancelled
+    pub fn check_cancelled(&self, id: &str) -> Result<(), String> {
+        if self.token.is_cancelled() {
+            return self.cancel(id);
+        }
+        Ok(())
+    }
+
+    fn cancel<T>(&self, id: &str) -> Result<T, String> {
+        info!("Operation {} cancelled at step {}", self.id, id);
+        self.update_history(|history| {
+            history.end_step(id, "cancelled");
+            history.status = "cancelled".to_string();
+        });
+        self.window
+            .emit(
+                &format!("operation_{}", self.id),
+                OperationUpdate {
+                    run_id: self.run_id,
+                    update_type: "cancelled",
+                    step_id: id,
+                    extra_details: None,
+                    progress: None,
+                },
+            )
+            .map_err(|_| "Failed to emit status to frontend".to_string())?;
+        Err(CANCELLED.to_string())
+    }
+
     pub fn start(&self, id: &str) -> Result<(), String> {
+        self.update_info(|info| {
+            info.step = Some(id.to_string());
+            info.progress = None;
+        });
+        self.update_history(|history| history.start_step(id));
         self.window
             .emit(
                 &format!("operation_{}", self.id),
                 OperationUpdate {
+                    run_id: self.run_id,
                     update_type: "started",
                     step_id: id,
                     extra_details: None,
+                    progress: None,
                 },
             )
             .map_err(|_| "Failed to emit status to frontend".to_string())
     }
 
+    // Reports how far along the running step `id` is, skipping repeats of the same percentage
+    pub fn progress(&self, id: &str, percent: u64) -> Result<(), String> {
+        let percent = percent.min(100);
+        let mut changed = false;
+        self.update_info(|info| {
+            changed = info.progress != Some(percent);
+            info.progress = Some(percent);
+        });
+        if !changed {
+            return Ok(());
+        }
+        self.window
+            .emit(
+                &format!("operation_{}", self.id),
+                OperationUpdate {
+                    run_id: self.run_id,
+                    update_type: "progress",
+                    step_id: id,
+                    extra_details: None,
+                    progress: Some(percent),
+                },
+            )
+            .map_err(|_| "Failed to emit status to frontend".to_string())
+    }
+
+    // Runs `fut` while reporting the percentages it sends through `rx` as progress of `id`. The
+    // sender has to be dropped when `fut` finishes.
+    pub async fn track<T>(
+        &self,
+        id: &str,
+        mut rx: UnboundedReceiver<u64>,
+        fut: impl Future<Output = T>,
+    ) -> T {
+        let forward = async {
+            while let Some(percent) = rx.recv().await {
+                let _ = self.progress(id, percent);
+            }
+        };
+        let (res, _) = futures::join!(fut, forward);
+        res
+    }
+
     pub fn complete(&self, id: &str) -> Result<(), String> {
+        self.update_history(|history| history.end_step(id, "finished"));
         self.window
             .emit(
                 &format!("operation_{}", self.id),
                 OperationUpdate {
+                    run_id: self.run_id,
                     update_type: "finished",
                     step_id: id,
                     extra_details: None,
+                    progress: None,
                 },
             )
             .map_err(|_| "Failed to emit status to frontend".to_string())
     }
 
     pub fn fail<T>(&self, id: &str, error: String) -> Result<T, String> {
+        // Whatever failed after a cancellation most likely failed because of it
+        if self.token.is_cancelled() {
+            return self.cancel(id);
+        }
+        self.update_history(|history| {
+            history.end_step(id, "failed");
+            history.status = "failed".to_string();
+            history.error = Some(error.clone());
+        });
         self.window
             .emit(
                 &format!("operation_{}", self.id),
                 OperationUpdate {
+                    run_id: self.run_id,
                     update_type: "failed",
                     step_id: id,
                     extra_details: Some(error.clone()),
+                    progress: None,
                 },
             )
             .map_err(|_| "Failed to emit status to frontend".to_string())?;
@@ -71,3 +383,45 @@ impl<'a> Operation<'a> {
         }
     }
 }
+
+impl Drop for Operation<'_> {
+    fn drop(&mut self) {
+        RUNNING.lock().unwrap().remove(&self.run_id);
+        let history = self.history.get_mut().unwrap();
+        history.finish();
+        record_run(self.window.app_handle(), history);
+    }
+}
+
+// Reserves a run ID for the frontend to pass to the operation it is about to start, so it only
+// listens to that run's events and can cancel just that run
+#[tauri::command]
+pub fn reserve_run_id() -> u64 {
+    NEXT_RUN_ID.fetch_add(1, Ordering::SeqCst)
+}
+
+// Asks the running or queued operation with this run ID to stop. It stops at the next step or
+// the next downloaded chunk; returns false if it wasn't running.
+#[tauri::command]
+pub fn cancel_operation(run_id: u64) -> bool {
+    match RUNNING.lock().unwrap().get(&run_id) {
+        Some(op) => {
+            info!("Cancelling operation {} (run {})", op.info.id, run_id);
+            op.token.0.store(true, Ordering::SeqCst);
+            true
+        }
+        None => false,
+    }
+}
+
+#[tauri::command]
+pub fn list_operations() -> Vec<OperationInfo> {
+    let mut operations: Vec<OperationInfo> = RUNNING
+        .lock()
+        .unwrap()
+        .values()
+        .map(|op| op.info.clone())
+        .collect();
+    operations.sort_by_key(|op| op.run_id);
+    operations
+}
@@ -1,5 +1,3 @@
-use std::collections::HashMap;
-
 // used https://github.com/jkcoxson/idevice_pair/ as a guide
 use idevice::{
     house_arrest::HouseArrestClient, installation_proxy::InstallationProxyClient,
@@ -127,7 +125,7 @@ pub async fn place_pairing_cmd(
     place_pairing(device, bundle_id, path).await
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct PairingAppInfo {
     pub name: String,
@@ -135,18 +133,14 @@ pub struct PairingAppInfo {
     pub path: String,
 }
 
-#[tauri::command]
-pub async fn installed_pairing_apps(
-    device_state: State<'_, DeviceInfoMutex>,
+// Every installed user app whose display name is one of `names`, in PAIRING_APPS order. The same
+// app can be installed several times under different bundle IDs (e.g. signed by another account),
+// so all copies are returned.
+async fn find_pairing_apps(
+    device: &DeviceInfo,
+    names: &[&str],
 ) -> Result<Vec<PairingAppInfo>, String> {
-    let device = {
-        let device_guard = device_state.lock().unwrap();
-        match &*device_guard {
-            Some(d) => d.clone(),
-            None => return Err("No device selected".to_string()),
-        }
-    };
-    let provider = get_provider(&device).await?;
+    let provider = get_provider(device).await?;
     let mut installation_proxy = InstallationProxyClient::connect(&provider)
         .await
         .map_err(|e| format!("Failed to connect to installation proxy: {}", e))?;
@@ -156,21 +150,22 @@ pub async fn installed_pairing_apps(
         .await
         .map_err(|e| format!("Failed to get installed apps: {}", e))?;
 
-    let mut installed = HashMap::new();
+    let mut installed = Vec::new();
     for (bundle_id, app) in installed_apps {
         let n = app
             .as_dictionary()
             .and_then(|x| x.get("CFBundleDisplayName").and_then(|x| x.as_string()))
             .ok_or("Failed to parse installed apps".to_string())?;
 
-        if PAIRING_APPS.iter().any(|(name, _)| name == &n) {
-            installed.insert(n.to_string(), bundle_id);
+        if names.contains(&n) {
+            installed.push((n.to_string(), bundle_id));
         }
     }
+    installed.sort();
 
     let mut result = Vec::new();
     for (name, path) in PAIRING_APPS {
-        if let Some(bundle_id) = installed.get(*name) {
+        for (_, bundle_id) in installed.iter().filter(|(n, _)| n == name) {
             result.push(PairingAppInfo {
                 name: name.to_string(),
                 bundle_id: bundle_id.to_string(),
@@ -181,38 +176,54 @@ pub async fn installed_pairing_apps(
     Ok(result)
 }
 
+#[tauri::command]
+pub async fn installed_pairing_apps(
+    device_state: State<'_, DeviceInfoMutex>,
+) -> Result<Vec<PairingAppInfo>, String> {
+    let device = {
+        let device_guard = device_state.lock().unwrap();
+        match &*device_guard {
+            Some(d) => d.clone(),
+            None => return Err("No device selected".to_string()),
+        }
+    };
+    let names: Vec<&str> = PAIRING_APPS.iter().map(|(name, _)| *name).collect();
+    find_pairing_apps(&device, &names).await
+}
+
+// Where the pairing file goes after SideStore is installed
+pub enum PairingTarget {
+    Installed(PairingAppInfo),
+    // The new install wasn't found, but these were. Other installs are never chosen
+    // automatically, so the user picks one.
+    Candidates(Vec<PairingAppInfo>),
+}
+
+// The SideStore (or LiveContainer) install with `installed_bundle_id`, which receives the
+// pairing file, or every install found if that one isn't among them
 pub async fn get_sidestore_info(
     device: DeviceInfo,
     live_container: bool,
-) -> Result<Option<PairingAppInfo>, String> {
-    let provider = get_provider(&device).await?;
-    let mut installation_proxy = InstallationProxyClient::connect(&provider)
-        .await
-        .map_err(|e| format!("Failed to connect to installation proxy: {}", e))?;
-
-    let installed_apps = installation_proxy
-        .get_apps(Some("User"), None)
-        .await
-        .map_err(|e| format!("Failed to get installed apps: {}", e))?;
-
-    for (bundle_id, app) in installed_apps {
-        let n = app
-            .as_dictionary()
-            .and_then(|x| x.get("CFBundleDisplayName").and_then(|x| x.as_string()))
-            .ok_or("Failed to parse installed apps".to_string())?;
+    installed_bundle_id: &str,
+) -> Result<PairingTarget, String> {
+    let names: &[&str] = if live_container {
+        &["SideStore", "LiveContainer"]
+    } else {
+        &["SideStore"]
+    };
+    let candidates = find_pairing_apps(&device, names).await?;
 
-        if n == "SideStore" || (live_container && n == "LiveContainer") {
-            return Ok(Some(PairingAppInfo {
-                name: n.to_string(),
-                bundle_id: bundle_id.to_string(),
-                path: PAIRING_APPS
-                    .iter()
-                    .find(|(name, _)| name == &n)
-                    .map(|(_, path)| path.to_string())
-                    .unwrap_or_default(),
-            }));
-        }
+    if let Some(exact) = candidates
+        .iter()
+        .find(|c| c.bundle_id == installed_bundle_id)
+    {
+        return Ok(PairingTarget::Installed(exact.clone()));
     }
-
-    Ok(None)
+    if candidates.is_empty() {
+        return Err(format!(
+            "Could not find SideStore installed as {}",
+            installed_bundle_id
+        ));
+    }
+    Ok(PairingTarget::Candidates(candidates))
 }
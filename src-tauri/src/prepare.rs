@@ -0,0 +1,524 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Manager};
+use tauri_plugin_store::StoreExt;
+use zip::ZipArchive;
+
+use crate::{
+    files::walk_local,
+    inject::{inject, InjectionReport},
+    ipa::{normalize_png, plist_string, read_bundle_id, AppBundle},
+};
+
+// Changes applied to an app bundle before it is handed to isideload for signing
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppModifications {
+    #[serde(default)]
+    pub bundle_id: Option<String>,
+    // Install under `bundle_id` exactly as given instead of with the team ID appended
+    #[serde(default)]
+    pub exact_bundle_id: bool,
+    #[serde(default)]
+    pub display_name: Option<String>,
+    #[serde(default)]
+    pub strip_all_extensions: bool,
+    // Bundle IDs or bundle paths as reported by inspect_ipa
+    #[serde(default)]
+    pub strip_extensions: Vec<String>,
+    // Dylibs, frameworks and .deb tweaks to inject
+    #[serde(default)]
+    pub inject: Vec<String>,
+    // CydiaSubstrate.framework to bundle for injected tweaks
+    #[serde(default)]
+    pub substrate: Option<String>,
+    // PNG to use as the home screen icon
+    #[serde(default)]
+    pub icon: Option<String>,
+}
+
+impl AppModifications {
+    pub fn is_empty(&self) -> bool {
+        self.bundle_id.is_none()
+            && self.display_name.is_none()
+            && !self.strip_all_extensions
+            && self.strip_extensions.is_empty()
+            && self.inject.is_empty()
+            && self.substrate.is_none()
+            && self.icon.is_none()
+    }
+}
+
+pub struct PreparedApp {
+    pub path: PathBuf,
+    pub original_bundle_id: String,
+    pub bundle_id: String,
+    pub exact_bundle_id: bool,
+    pub removed_extensions: Vec<String>,
+    pub injection: Option<InjectionReport>,
+    staging: PathBuf,
+}
+
+// The bundle ID an app is signed and installed under. Like isideload, the team ID is appended so
+// the App ID doesn't clash with other teams' unless an exact override was asked for.
+pub fn installed_bundle_id(bundle_id: &str, team_id: &str, exact: bool) -> String {
+    if exact {
+        bundle_id.to_string()
+    } else {
+        format!("{}.{}", bundle_id, team_id)
+    }
+}
+
+impl PreparedApp {
+    pub fn installed_bundle_id(&self, team_id: &str) -> String {
+        installed_bundle_id(&self.bundle_id, team_id, self.exact_bundle_id)
+    }
+
+    // Where the signed app is packaged for upload, removed with the rest of the staging dir
+    pub fn package_path(&self) -> PathBuf {
+        self.staging.join("signed.ipa")
+    }
+
+    pub fn profile_path(&self) -> PathBuf {
+        self.staging.join("signing.mobileprovision")
+    }
+
+    pub fn entitlements_path(&self) -> PathBuf {
+        self.staging.join("entitlements.plist")
+    }
+}
+
+impl Drop for PreparedApp {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.staging);
+    }
+}
+
+// Overrides are remembered per source IPA so refreshing the same file later produces the same
+// identifiers. An explicit empty string clears a saved override. Nothing is saved until
+// remember_modifications is called once the app is installed.
+pub fn resolve_modifications(
+    handle: &AppHandle,
+    source: &str,
+    bundle_id: Option<String>,
+    exact_bundle_id: Option<bool>,
+    display_name: Option<String>,
+) -> Result<AppModifications, String> {
+    let store = handle
+        .store("data.json")
+        .map_err(|e| format!("Failed to get store: {:?}", e))?;
+    let previous: AppModifications = store
+        .get("bundleOverrides")
+        .and_then(|v| v.get(source).cloned())
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default();
+
+    let pick = |given: Option<String>, previous: Option<String>| match given {
+        Some(v) if v.trim().is_empty() => None,
+        Some(v) => Some(v.trim().to_string()),
+        None => previous,
+    };
+    let bundle_id = pick(bundle_id, previous.bundle_id);
+    Ok(AppModifications {
+        exact_bundle_id: bundle_id.is_some() && exact_bundle_id.unwrap_or(previous.exact_bundle_id),
+        bundle_id,
+        display_name: pick(display_name, previous.display_name),
+        ..Default::default()
+    })
+}
+
+// Saves the bundle ID and name overrides `source` was installed with for resolve_modifications
+pub fn remember_modifications(
+    handle: &AppHandle,
+    source: &str,
+    mods: &AppModifications,
+) -> Result<(), String> {
+    let store = handle
+        .store("data.json")
+        .map_err(|e| format!("Failed to get store: {:?}", e))?;
+    let mut saved = store
+        .get("bundleOverrides")
+        .and_then(|v| v.as_object().cloned())
+        .unwrap_or_default();
+
+    let overrides = AppModifications {
+        bundle_id: mods.bundle_id.clone(),
+        exact_bundle_id: mods.exact_bundle_id,
+        display_name: mods.display_name.clone(),
+        ..Default::default()
+    };
+    if overrides.is_empty() {
+        saved.remove(source);
+    } else {
+        saved.insert(
+            source.to_string(),
+            serde_json::to_value(&overrides).map_err(|e| e.to_string())?,
+        );
+    }
+    store.set("bundleOverrides", Value::Object(saved));
+    Ok(())
+}
+
+// Extracts the .app from an IPA (or copies an existing .app) into `dest` and returns its path.
+fn extract_app(app_path: &Path, dest: &Path) -> Result<PathBuf, String> {
+    if app_path.is_dir() {
+        let app_dir = dest.join(app_path.file_name().ok_or("Invalid app path".to_string())?);
+        let (dirs, files) = walk_local(app_path)?;
+        std::fs::create_dir_all(&app_dir).map_err(|e| format!("Failed to copy app: {}", e))?;
+        for dir in dirs {
+            std::fs::create_dir_all(app_dir.join(dir))
+                .map_err(|e| format!("Failed to copy app: {}", e))?;
+        }
+        for (local, rel) in files {
+            std::fs::copy(&local, app_dir.join(rel))
+                .map_err(|e| format!("Failed to copy app: {}", e))?;
+        }
+        return Ok(app_dir);
+    }
+
+    let file = File::open(app_path).map_err(|e| format!("Failed to open IPA: {}", e))?;
+    ZipArchive::new(file)
+        .and_then(|mut archive| archive.extract(dest))
+        .map_err(|e| format!("Failed to extract IPA: {}", e))?;
+
+    std::fs::read_dir(dest.join("Payload"))
+        .map_err(|e| format!("IPA does not contain a Payload folder: {}", e))?
+        .flatten()
+        .map(|entry| entry.path())
+        .find(|p| p.extension().is_some_and(|ext| ext == "app"))
+        .ok_or("IPA does not contain an app bundle".to_string())
+}
+
+fn edit_plist(
+    path: &Path,
+    edit: impl FnOnce(&mut plist::Dictionary) -> Result<(), String>,
+) -> Result<(), String> {
+    let mut dict: plist::Dictionary =
+        plist::from_file(path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+    edit(&mut dict)?;
+    plist::Value::Dictionary(dict)
+        .to_file_xml(path)
+        .map_err(|e| format!("Failed to write {:?}: {}", path, e))
+}
+
+fn replace_prefix(value: &str, old: &str, new: &str) -> Option<String> {
+    if value == old {
+        Some(new.to_string())
+    } else {
+        value
+            .strip_prefix(&format!("{}.", old))
+            .map(|rest| format!("{}.{}", new, rest))
+    }
+}
+
+fn rename_key(dict: &mut plist::Dictionary, key: &str, old: &str, new: &str) {
+    let renamed = dict
+        .get(key)
+        .and_then(|v| v.as_string())
+        .and_then(|v| replace_prefix(v, old, new));
+    if let Some(renamed) = renamed {
+        dict.insert(key.to_string(), plist::Value::String(renamed));
+    }
+}
+
+// Moves the main app and every extension and watch app from `old` to `new`, keeping the
+// extensions' suffixes so they remain children of the main bundle ID.
+fn rewrite_bundle_ids(app_dir: &Path, old: &str, new: &str) -> Result<(), String> {
+    let mut plists = vec![app_dir.join("Info.plist")];
+    plists.extend(
+        AppBundle::Dir(app_dir.to_path_buf())
+            .nested_bundles()?
+            .into_iter()
+            .map(|dir| app_dir.join(dir).join("Info.plist")),
+    );
+
+    for path in plists {
+        debug!("Rewriting bundle IDs in {:?}", path);
+        edit_plist(&path, |dict| {
+            rename_key(dict, "CFBundleIdentifier", old, new);
+            rename_key(dict, "WKCompanionAppBundleIdentifier", old, new);
+            if let Some(attributes) = dict
+                .get_mut("NSExtension")
+                .and_then(|v| v.as_dictionary_mut())
+                .and_then(|d| d.get_mut("NSExtensionAttributes"))
+                .and_then(|v| v.as_dictionary_mut())
+            {
+                rename_key(attributes, "WKAppBundleIdentifier", old, new);
+            }
+            Ok(())
+        })?;
+    }
+    Ok(())
+}
+
+// The icon iOS shows at the largest size, 60pt at @3x
+const MIN_ICON_SIZE: u32 = 180;
+
+// A decoded square icon as RGBA pixels
+pub struct Icon {
+    size: u32,
+    pixels: Vec<u8>,
+}
+
+// Reads a PNG (Xcode's CgBI variant included) and checks it can be used as an app icon
+pub fn load_icon(path: &Path) -> Result<Icon, String> {
+    let data = std::fs::read(path).map_err(|e| format!("Failed to read icon: {}", e))?;
+    let data = normalize_png(&data)?;
+    let mut decoder = png::Decoder::new(std::io::Cursor::new(data));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder
+        .read_info()
+        .map_err(|e| format!("Failed to decode icon: {}", e))?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let frame = reader
+        .next_frame(&mut buf)
+        .map_err(|e| format!("Failed to decode icon: {}", e))?;
+    buf.truncate(frame.buffer_size());
+
+    if frame.width != frame.height {
+        return Err(format!(
+            "The icon must be square, but it is {}x{}",
+            frame.width, frame.height
+        ));
+    }
+    if frame.width < MIN_ICON_SIZE {
+        return Err(format!(
+            "The icon must be at least {0}x{0} pixels, but it is {1}x{1}",
+            MIN_ICON_SIZE, frame.width
+        ));
+    }
+
+    let pixels = match frame.color_type {
+        png::ColorType::Rgba => buf,
+        png::ColorType::Rgb => buf
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => buf
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        png::ColorType::Grayscale => buf.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+        png::ColorType::Indexed => return Err("Failed to expand the icon's palette".to_string()),
+    };
+    Ok(Icon {
+        size: frame.width,
+        pixels,
+    })
+}
+
+impl Icon {
+    // Scales the icon down to `size` by averaging the pixels each target pixel covers
+    fn resized(&self, size: u32) -> Vec<u8> {
+        let (src, dst) = (self.size as usize, size as usize);
+        let mut out = Vec::with_capacity(dst * dst * 4);
+        for y in 0..dst {
+            let (y0, y1) = (y * src / dst, ((y + 1) * src / dst).max(y * src / dst + 1));
+            for x in 0..dst {
+                let (x0, x1) = (x * src / dst, ((x + 1) * src / dst).max(x * src / dst + 1));
+                let mut sum = [0usize; 4];
+                for sy in y0..y1 {
+                    for sx in x0..x1 {
+                        let i = (sy * src + sx) * 4;
+                        for (c, total) in sum.iter_mut().enumerate() {
+                            *total += self.pixels[i + c] as usize;
+                        }
+                    }
+                }
+                let count = (y1 - y0) * (x1 - x0);
+                out.extend(sum.iter().map(|total| (total / count) as u8));
+            }
+        }
+        out
+    }
+
+    fn write_png(&self, size: u32, dest: &Path) -> Result<(), String> {
+        let file = File::create(dest).map_err(|e| format!("Failed to write icon: {}", e))?;
+        let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), size, size);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&self.resized(size)))
+            .map_err(|e| format!("Failed to write icon: {}", e))
+    }
+}
+
+// Replaces the home screen icon. The icon files are referenced directly and the asset catalog
+// name is dropped, otherwise iOS keeps using the icon from Assets.car.
+fn replace_icon(app_dir: &Path, icon: &Path) -> Result<(), String> {
+    let icon = load_icon(icon)?;
+    for (scale, size) in [("@2x", 120), ("@3x", 180)] {
+        icon.write_png(size, &app_dir.join(format!("iloader-icon60x60{}.png", scale)))?;
+    }
+    let mut icons = plist::Dictionary::new();
+    let mut primary = plist::Dictionary::new();
+    primary.insert(
+        "CFBundleIconFiles".to_string(),
+        plist::Value::Array(vec!["iloader-icon60x60".into()]),
+    );
+    icons.insert(
+        "CFBundlePrimaryIcon".to_string(),
+        plist::Value::Dictionary(primary),
+    );
+
+    edit_plist(&app_dir.join("Info.plist"), |dict| {
+        dict.remove("CFBundleIconName");
+        dict.remove("CFBundleIconFiles");
+        dict.insert(
+            "CFBundleIcons".to_string(),
+            plist::Value::Dictionary(icons.clone()),
+        );
+        dict.insert(
+            "CFBundleIcons~ipad".to_string(),
+            plist::Value::Dictionary(icons),
+        );
+        Ok(())
+    })
+}
+
+fn is_under(dir: &str, root: &str) -> bool {
+    dir == root || dir.starts_with(&format!("{}/", root))
+}
+
+// Deletes the selected extensions and watch apps and returns the bundle IDs of everything that
+// was removed, including bundles nested inside a removed watch app.
+fn strip_extensions(app_dir: &Path, mods: &AppModifications) -> Result<Vec<String>, String> {
+    let bundles: Vec<(String, String)> = AppBundle::Dir(app_dir.to_path_buf())
+        .nested_bundles()?
+        .into_iter()
+        .map(|dir| {
+            let bundle_id = plist::from_file(app_dir.join(&dir).join("Info.plist"))
+                .ok()
+                .and_then(|info: plist::Dictionary| plist_string(&info, "CFBundleIdentifier"))
+                .unwrap_or_default();
+            (dir, bundle_id)
+        })
+        .collect();
+
+    for wanted in &mods.strip_extensions {
+        if !bundles
+            .iter()
+            .any(|(dir, id)| wanted == dir || wanted == id)
+        {
+            return Err(format!(
+                "The app does not contain an extension named {}",
+                wanted
+            ));
+        }
+    }
+
+    let selected: Vec<&String> = bundles
+        .iter()
+        .filter(|(dir, id)| {
+            mods.strip_all_extensions || mods.strip_extensions.iter().any(|s| s == dir || s == id)
+        })
+        .map(|(dir, _)| dir)
+        .collect();
+
+    let removed = bundles
+        .iter()
+        .filter(|(dir, _)| selected.iter().any(|root| is_under(dir, root)))
+        .map(|(_, id)| id.clone())
+        .collect();
+
+    for root in &selected {
+        if selected
+            .iter()
+            .any(|other| other != root && is_under(root, other))
+        {
+            continue;
+        }
+        info!("Removing {} from app", root);
+        std::fs::remove_dir_all(app_dir.join(root))
+            .map_err(|e| format!("Failed to remove {}: {}", root, e))?;
+    }
+
+    Ok(removed)
+}
+
+pub fn prepare_app(
+    handle: &AppHandle,
+    app_path: &Path,
+    mods: &AppModifications,
+) -> Result<PreparedApp, String> {
+    let original_bundle_id = read_bundle_id(app_path)?;
+    if !mods.is_empty() {
+        info!("Preparing {:?} with {:?}", app_path, mods);
+    }
+
+    // The app is always extracted so the signed bundle (and its provisioning profile) can be
+    // inspected after isideload is done with it
+    let staging = handle
+        .path()
+        .temp_dir()
+        .map_err(|e| format!("Failed to get temp dir: {:?}", e))?
+        .join(format!(
+            "iloader-prepare-{}",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ));
+    if staging.exists() {
+        std::fs::remove_dir_all(&staging)
+            .map_err(|e| format!("Failed to clear staging dir: {}", e))?;
+    }
+    std::fs::create_dir_all(&staging)
+        .map_err(|e| format!("Failed to create staging dir: {}", e))?;
+
+    let mut prepared = PreparedApp {
+        path: PathBuf::new(),
+        bundle_id: original_bundle_id.clone(),
+        original_bundle_id,
+        exact_bundle_id: false,
+        removed_extensions: vec![],
+        injection: None,
+        staging: staging.clone(),
+    };
+    prepared.path = extract_app(app_path, &staging)?;
+
+    // Stripping goes first so extensions can be selected by their original bundle IDs
+    if mods.strip_all_extensions || !mods.strip_extensions.is_empty() {
+        prepared.removed_extensions = strip_extensions(&prepared.path, mods)?;
+    }
+
+    if let Some(bundle_id) = &mods.bundle_id {
+        rewrite_bundle_ids(&prepared.path, &prepared.original_bundle_id, bundle_id)?;
+        prepared.bundle_id = bundle_id.clone();
+        prepared.exact_bundle_id = mods.exact_bundle_id;
+    }
+
+    if let Some(display_name) = &mods.display_name {
+        edit_plist(&prepared.path.join("Info.plist"), |dict| {
+            dict.insert(
+                "CFBundleDisplayName".to_string(),
+                plist::Value::String(display_name.clone()),
+            );
+            Ok(())
+        })?;
+    }
+
+    if let Some(icon) = &mods.icon {
+        replace_icon(&prepared.path, Path::new(icon))?;
+    }
+
+    // Injection runs before isideload signs, which also signs the injected binaries
+    if !mods.inject.is_empty() || mods.substrate.is_some() {
+        prepared.injection = Some(inject(
+            &prepared.path,
+            &staging,
+            &mods.inject,
+            mods.substrate.as_deref(),
+        )?);
+    }
+
+    Ok(prepared)
+}
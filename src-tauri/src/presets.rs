@@ -0,0 +1,187 @@
+use std::{collections::BTreeMap, path::Path};
+
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::AppHandle;
+use tauri_plugin_store::StoreExt;
+
+use crate::prepare::{load_icon, AppModifications};
+
+// Entitlements to add (or replace) and remove when signing
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct EntitlementOverrides {
+    pub add: BTreeMap<String, Value>,
+    pub remove: Vec<String>,
+}
+
+impl EntitlementOverrides {
+    pub fn to_plist(&self) -> Result<plist::Dictionary, String> {
+        let mut dict = plist::Dictionary::new();
+        for (key, value) in &self.add {
+            dict.insert(
+                key.clone(),
+                json_to_plist(value).ok_or(format!("Entitlement {} has no value", key))?,
+            );
+        }
+        Ok(dict)
+    }
+}
+
+// Signing settings applied automatically whenever an app with `bundle_id` is installed
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SigningPreset {
+    // The app's own bundle ID, before any override
+    pub bundle_id: String,
+    pub name: Option<String>,
+    pub bundle_id_override: Option<String>,
+    pub display_name: Option<String>,
+    pub strip_all_extensions: bool,
+    pub strip_extensions: Vec<String>,
+    // Path to a PNG used as the home screen icon
+    pub icon: Option<String>,
+    pub entitlements: EntitlementOverrides,
+}
+
+impl SigningPreset {
+    // Fills in whatever the caller didn't set explicitly
+    pub fn apply(&self, mods: &mut AppModifications) {
+        if mods.bundle_id.is_none() {
+            mods.bundle_id = self.bundle_id_override.clone();
+        }
+        if mods.display_name.is_none() {
+            mods.display_name = self.display_name.clone();
+        }
+        if mods.icon.is_none() {
+            mods.icon = self.icon.clone();
+        }
+        mods.strip_all_extensions |= self.strip_all_extensions;
+        for ext in &self.strip_extensions {
+            if !mods.strip_extensions.contains(ext) {
+                mods.strip_extensions.push(ext.clone());
+            }
+        }
+    }
+}
+
+fn json_to_plist(value: &Value) -> Option<plist::Value> {
+    Some(match value {
+        Value::Null => return None,
+        Value::Bool(b) => plist::Value::Boolean(*b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => plist::Value::Integer(i.into()),
+            None => plist::Value::Real(n.as_f64()?),
+        },
+        Value::String(s) => plist::Value::String(s.clone()),
+        Value::Array(items) => {
+            plist::Value::Array(items.iter().filter_map(json_to_plist).collect())
+        }
+        Value::Object(map) => plist::Value::Dictionary(
+            map.iter()
+                .filter_map(|(k, v)| Some((k.clone(), json_to_plist(v)?)))
+                .collect(),
+        ),
+    })
+}
+
+pub fn load_presets(handle: &AppHandle) -> Result<Vec<SigningPreset>, String> {
+    let store = handle
+        .store("presets.json")
+        .map_err(|e| format!("Failed to get store: {:?}", e))?;
+    Ok(store
+        .get("presets")
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default())
+}
+
+fn save_presets(handle: &AppHandle, presets: &[SigningPreset]) -> Result<(), String> {
+    let store = handle
+        .store("presets.json")
+        .map_err(|e| format!("Failed to get store: {:?}", e))?;
+    store.set(
+        "presets",
+        serde_json::to_value(presets).map_err(|e| e.to_string())?,
+    );
+    Ok(())
+}
+
+pub fn preset_for(handle: &AppHandle, bundle_id: &str) -> Result<Option<SigningPreset>, String> {
+    Ok(load_presets(handle)?
+        .into_iter()
+        .find(|p| p.bundle_id == bundle_id))
+}
+
+fn validate(preset: &SigningPreset) -> Result<(), String> {
+    if preset.bundle_id.trim().is_empty() {
+        return Err("A preset needs a bundle ID".to_string());
+    }
+    if let Some(icon) = &preset.icon {
+        load_icon(Path::new(icon))?;
+    }
+    preset.entitlements.to_plist().map(|_| ())
+}
+
+fn upsert(presets: &mut Vec<SigningPreset>, preset: SigningPreset) {
+    presets.retain(|p| p.bundle_id != preset.bundle_id);
+    presets.push(preset);
+    presets.sort_by(|a, b| a.bundle_id.cmp(&b.bundle_id));
+}
+
+#[tauri::command]
+pub fn list_presets(handle: AppHandle) -> Result<Vec<SigningPreset>, String> {
+    load_presets(&handle)
+}
+
+// Creates the preset for its bundle ID, or replaces the existing one
+#[tauri::command]
+pub fn save_preset(handle: AppHandle, preset: SigningPreset) -> Result<(), String> {
+    validate(&preset)?;
+    let mut presets = load_presets(&handle)?;
+    info!("Saving signing preset for {}", preset.bundle_id);
+    upsert(&mut presets, preset);
+    save_presets(&handle, &presets)
+}
+
+#[tauri::command]
+pub fn delete_preset(handle: AppHandle, bundle_id: String) -> Result<(), String> {
+    let mut presets = load_presets(&handle)?;
+    presets.retain(|p| p.bundle_id != bundle_id);
+    save_presets(&handle, &presets)
+}
+
+#[tauri::command]
+pub fn export_presets(
+    handle: AppHandle,
+    path: String,
+    bundle_ids: Option<Vec<String>>,
+) -> Result<usize, String> {
+    let mut presets = load_presets(&handle)?;
+    if let Some(bundle_ids) = bundle_ids {
+        presets.retain(|p| bundle_ids.contains(&p.bundle_id));
+    }
+    let json = serde_json::to_string_pretty(&presets).map_err(|e| e.to_string())?;
+    std::fs::write(&path, json).map_err(|e| format!("Failed to write {}: {}", path, e))?;
+    Ok(presets.len())
+}
+
+// Adds the presets in a file exported by export_presets, replacing ones for the same bundle IDs
+#[tauri::command]
+pub fn import_presets(handle: AppHandle, path: String) -> Result<usize, String> {
+    let json =
+        std::fs::read_to_string(&path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let imported: Vec<SigningPreset> =
+        serde_json::from_str(&json).map_err(|e| format!("Invalid presets file: {}", e))?;
+    for preset in &imported {
+        validate(preset)?;
+    }
+
+    let count = imported.len();
+    let mut presets = load_presets(&handle)?;
+    for preset in imported {
+        upsert(&mut presets, preset);
+    }
+    save_presets(&handle, &presets)?;
+    Ok(count)
+}
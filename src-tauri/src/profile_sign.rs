@@ -0,0 +1,417 @@
+use std::path::Path;
+
+use apple_codesign::{
+    cryptography::InMemoryPrivateKey, BundleSigner, SettingsScope, SigningSettings,
+};
+use log::{debug, error, info, warn};
+use openssl::pkcs12::Pkcs12;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tauri::{AppHandle, State, Window};
+use tokio::sync::mpsc::unbounded_channel;
+use x509_certificate::CapturedX509Certificate;
+
+use crate::{
+    apps::normalize_serial,
+    backup::backup_if_installed,
+    device::{DeviceInfo, DeviceInfoMutex},
+    install::{install_package, upload_package},
+    inventory::now,
+    ipa::{parse_provisioning_profile, plist_string, read_info_plist, AppBundle, ProfileInfo},
+    operation::Operation,
+    prepare::{prepare_app, remember_modifications, PreparedApp},
+    presets::EntitlementOverrides,
+    sideload::SideloadParams,
+    sign::write_ipa,
+    signing::check_entitlements,
+};
+
+// A certificate and profiles made outside iloader, for example exported from Xcode
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SigningCredentials {
+    pub p12_path: String,
+    pub p12_password: String,
+    pub profile_paths: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileAssignment {
+    // Relative to the .app, empty for the app itself
+    pub bundle_path: String,
+    pub bundle_id: String,
+    pub profile_name: Option<String>,
+    pub profile_path: String,
+    pub team_id: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileSignReport {
+    pub bundle_id: String,
+    pub team_id: Option<String>,
+    pub profiles: Vec<ProfileAssignment>,
+    pub removed_extensions: Vec<String>,
+}
+
+struct LoadedProfile {
+    path: String,
+    data: Vec<u8>,
+    info: ProfileInfo,
+}
+
+fn load_profiles(paths: &[String]) -> Result<Vec<LoadedProfile>, String> {
+    if paths.is_empty() {
+        return Err("At least one provisioning profile is required".to_string());
+    }
+    paths
+        .iter()
+        .map(|path| {
+            let data =
+                std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+            let info = parse_provisioning_profile(&data)
+                .ok_or(format!("{} is not a provisioning profile", path))?;
+            Ok(LoadedProfile {
+                path: path.clone(),
+                data,
+                info,
+            })
+        })
+        .collect()
+}
+
+// How well the profile's App ID covers `bundle_id`: an exact match beats any wildcard, and longer
+// wildcard prefixes beat shorter ones
+fn match_score(profile: &ProfileInfo, bundle_id: &str) -> Option<usize> {
+    let app_id = profile.application_identifier.as_deref()?;
+    let (_, pattern) = app_id.split_once('.')?;
+    if pattern == bundle_id {
+        return Some(usize::MAX);
+    }
+    let prefix = pattern.strip_suffix('*')?;
+    bundle_id.starts_with(prefix).then_some(prefix.len())
+}
+
+fn profile_for<'a>(profiles: &'a [LoadedProfile], bundle_id: &str) -> Option<&'a LoadedProfile> {
+    profiles
+        .iter()
+        .filter_map(|p| Some((match_score(&p.info, bundle_id)?, p)))
+        .max_by_key(|(score, _)| *score)
+        .map(|(_, p)| p)
+}
+
+// The profile's entitlements with its wildcards narrowed down to the bundle being signed
+fn bundle_entitlements(
+    profile: &ProfileInfo,
+    bundle_id: &str,
+) -> Result<plist::Dictionary, String> {
+    let mut entitlements = profile.entitlements.clone().unwrap_or_default();
+    let team_id = profile
+        .team_id
+        .clone()
+        .ok_or("The profile has no team ID".to_string())?;
+    let app_id = format!("{}.{}", team_id, bundle_id);
+    entitlements.insert("application-identifier".to_string(), app_id.clone().into());
+    if let Some(groups) = entitlements
+        .get_mut("keychain-access-groups")
+        .and_then(|v| v.as_array_mut())
+    {
+        for group in groups.iter_mut() {
+            if group.as_string().is_some_and(|g| g.ends_with('*')) {
+                *group = app_id.clone().into();
+            }
+        }
+    }
+    Ok(entitlements)
+}
+
+fn entitlements_xml(entitlements: plist::Dictionary) -> Result<String, String> {
+    let mut xml = Vec::new();
+    plist::Value::Dictionary(entitlements)
+        .to_writer_xml(&mut xml)
+        .map_err(|e| format!("Failed to write entitlements: {}", e))?;
+    String::from_utf8(xml).map_err(|e| format!("Failed to write entitlements: {}", e))
+}
+
+// The serial, certificate and key in a .p12. It is read with OpenSSL, which handles both the
+// legacy encryption Keychain exports use and the AES one OpenSSL 3 writes, e.g. for identities
+// exported from iloader. apple-codesign's own reader only supports the legacy one.
+fn load_identity(
+    p12: &[u8],
+    password: &str,
+) -> Result<(String, CapturedX509Certificate, InMemoryPrivateKey), String> {
+    let parsed = Pkcs12::from_der(p12)
+        .and_then(|p| p.parse2(password))
+        .map_err(|_| "The file is not a .p12 or the password is wrong".to_string())?;
+    let (Some(cert), Some(key)) = (parsed.cert, parsed.pkey) else {
+        return Err("The .p12 doesn't contain a certificate and its key".to_string());
+    };
+    let serial = cert
+        .serial_number()
+        .to_bn()
+        .and_then(|bn| bn.to_hex_str().map(|s| normalize_serial(&s)))
+        .map_err(|e| format!("Failed to read the certificate: {}", e))?;
+    let cert = cert
+        .to_der()
+        .map_err(|e| e.to_string())
+        .and_then(|der| CapturedX509Certificate::from_der(der).map_err(|e| e.to_string()))
+        .map_err(|e| format!("Failed to read the certificate: {}", e))?;
+    let key = key
+        .private_key_to_pkcs8()
+        .map_err(|e| e.to_string())
+        .and_then(|der| InMemoryPrivateKey::from_pkcs8_der(der).map_err(|e| e.to_string()))
+        .map_err(|e| format!("Failed to read the signing key: {}", e))?;
+    Ok((serial, cert, key))
+}
+
+// Picks a profile for the app and each extension, embeds it and signs the whole bundle with the
+// certificate from the .p12. `overrides` apply to the app itself, like when signing with an
+// Apple ID.
+fn sign_with_profiles(
+    prepared: &PreparedApp,
+    credentials: &SigningCredentials,
+    udid: &str,
+    overrides: &EntitlementOverrides,
+) -> Result<Vec<ProfileAssignment>, String> {
+    let p12 = std::fs::read(&credentials.p12_path)
+        .map_err(|e| format!("Failed to read {}: {}", credentials.p12_path, e))?;
+    let (serial, cert, key) = load_identity(&p12, &credentials.p12_password)?;
+    let profiles = load_profiles(&credentials.profile_paths)?;
+
+    let mut bundles = vec![String::new()];
+    bundles.extend(AppBundle::open(&prepared.path)?.nested_bundles()?);
+
+    let mut settings = SigningSettings::default();
+    settings.set_signing_key(&key, cert);
+    settings.chain_apple_certificates();
+    settings.set_team_id_from_signing_certificate();
+
+    let mut assignments = vec![];
+    for bundle_path in bundles {
+        let dir = prepared.path.join(&bundle_path);
+        let bundle_id = plist_string(&read_info_plist(&dir)?, "CFBundleIdentifier")
+            .ok_or(format!("{} has no bundle ID", dir.to_string_lossy()))?;
+        let profile = profile_for(&profiles, &bundle_id)
+            .ok_or(format!("None of the profiles can sign {}", bundle_id))?;
+
+        if !profile
+            .info
+            .certificate_serials
+            .iter()
+            .any(|s| normalize_serial(s) == serial)
+        {
+            return Err(format!(
+                "The profile for {} doesn't include the certificate in the .p12",
+                bundle_id
+            ));
+        }
+        if !profile.info.provisions_all_devices
+            && !profile
+                .info
+                .provisioned_devices
+                .iter()
+                .any(|d| d.eq_ignore_ascii_case(udid))
+        {
+            return Err(format!(
+                "The profile for {} doesn't include this device ({})",
+                bundle_id, udid
+            ));
+        }
+        if profile.info.expiration.is_some_and(|expiry| expiry < now()) {
+            return Err(format!("The profile for {} has expired", bundle_id));
+        }
+
+        std::fs::write(dir.join("embedded.mobileprovision"), &profile.data)
+            .map_err(|e| format!("Failed to embed the profile of {}: {}", bundle_id, e))?;
+        let mut entitlements = bundle_entitlements(&profile.info, &bundle_id)?;
+        let scope = if bundle_path.is_empty() {
+            let extra = overrides.to_plist()?;
+            check_entitlements(&entitlements, &extra)?;
+            entitlements.extend(extra);
+            for key in &overrides.remove {
+                entitlements.remove(key);
+            }
+            SettingsScope::Main
+        } else {
+            SettingsScope::Path(bundle_path.clone())
+        };
+        settings
+            .set_entitlements_xml(scope.clone(), entitlements_xml(entitlements)?)
+            .map_err(|e| format!("Invalid entitlements for {}: {}", bundle_id, e))?;
+        settings.set_binary_identifier(scope, &bundle_id);
+
+        info!("Signing {} with profile {:?}", bundle_id, profile.info.name);
+        assignments.push(ProfileAssignment {
+            bundle_path,
+            bundle_id,
+            profile_name: profile.info.name.clone(),
+            profile_path: profile.path.clone(),
+            team_id: profile.info.team_id.clone(),
+        });
+    }
+
+    // Nested bundles are signed before the app that contains them
+    let signer = BundleSigner::new_from_path(&prepared.path)
+        .map_err(|e| format!("Failed to read app bundle: {}", e))?;
+    signer
+        .write_signed_bundle(&prepared.path, &settings)
+        .map_err(|e| format!("Failed to sign app: {}", e))?;
+    Ok(assignments)
+}
+
+// Signs with the given credentials and installs, going through "prepare", "sign", "upload" and
+// "install" without logging in to Apple
+async fn sign_and_install(
+    op: &Operation<'_>,
+    handle: &AppHandle,
+    device: &DeviceInfo,
+    app_path: &str,
+    credentials: &SigningCredentials,
+    params: SideloadParams,
+) -> Result<ProfileSignReport, String> {
+    let options = op.fail_if_err("prepare", params.into_options(handle, app_path))?;
+    let (modifications, overrides) =
+        op.fail_if_err("prepare", options.with_preset(handle, app_path))?;
+    let prepared = op.fail_if_err(
+        "prepare",
+        prepare_app(handle, Path::new(app_path), &modifications),
+    )?;
+
+    op.move_on("prepare", "sign")?;
+    let profiles = op.fail_if_err(
+        "sign",
+        sign_with_profiles(&prepared, credentials, &device.uuid, &overrides),
+    )?;
+    let team_id = profiles.first().and_then(|a| a.team_id.clone());
+
+    op.move_on("sign", "upload")?;
+    let package = prepared.package_path();
+    op.fail_if_err("upload", write_ipa(&prepared.path, &package))?;
+    let remote_path = op.fail_if_err(
+        "upload",
+        upload_package(device, &package, |percent| {
+            let _ = op.progress("upload", percent);
+        })
+        .await,
+    )?;
+
+    op.move_on("upload", "install")?;
+    if options.backup_before_install {
+        match backup_if_installed(handle, device, std::slice::from_ref(&prepared.bundle_id)).await {
+            Ok(Some(path)) => info!("Backed up existing app data to {:?}", path),
+            Ok(None) => debug!("App is not installed yet, nothing to back up"),
+            Err(e) => {
+                error!("Failed to back up app data: {}", e);
+                return op.fail(
+                    "install",
+                    format!("Failed to back up existing app data: {}", e),
+                );
+            }
+        }
+    }
+
+    let (tx, rx) = unbounded_channel();
+    op.fail_if_err(
+        "install",
+        op.track(
+            "install",
+            rx,
+            install_package(device, &remote_path, move |percent| {
+                let _ = tx.send(percent);
+            }),
+        )
+        .await,
+    )?;
+    if let Some(source) = &options.remember_as {
+        if let Err(e) = remember_modifications(handle, source, &options.modifications) {
+            warn!("Failed to save the overrides of {}: {}", source, e);
+        }
+    }
+
+    Ok(ProfileSignReport {
+        bundle_id: prepared.bundle_id.clone(),
+        team_id,
+        profiles,
+        removed_extensions: prepared.removed_extensions.clone(),
+    })
+}
+
+// Installs an app signed with a certificate and profiles the user already has. Apps installed
+// this way aren't added to the inventory since refreshing them needs new profiles.
+#[tauri::command]
+pub async fn sign_with_profile_operation(
+    handle: AppHandle,
+    window: Window,
+    run_id: Option<u64>,
+    device_state: State<'_, DeviceInfoMutex>,
+    app_path: String,
+    credentials: SigningCredentials,
+    options: Option<SideloadParams>,
+) -> Result<ProfileSignReport, String> {
+    let op = Operation::new("sign_with_profile".to_string(), &window, run_id);
+    op.set_params(json!({
+        "appPath": app_path,
+        "p12Path": credentials.p12_path,
+        "profilePaths": credentials.profile_paths,
+        "options": options,
+    }));
+    op.start("prepare")?;
+    let device = {
+        let device_guard = device_state.lock().unwrap();
+        match &*device_guard {
+            Some(d) => d.clone(),
+            None => return op.fail("prepare", "No device selected".to_string()),
+        }
+    };
+    op.wait_for("prepare", Some(&device.uuid), None).await?;
+
+    let report = sign_and_install(
+        &op,
+        &handle,
+        &device,
+        &app_path,
+        &credentials,
+        options.unwrap_or_default(),
+    )
+    .await?;
+    info!(
+        "Installed {} with {} profiles",
+        report.bundle_id,
+        report.profiles.len()
+    );
+    op.complete("install")?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ipa::test_support::certificate;
+
+    // A .p12 built the way export_identity builds one, with OpenSSL's default AES encryption
+    fn exported_p12(password: &str) -> (Vec<u8>, Vec<u8>) {
+        let (cert, key) = certificate(0x1abc);
+        let p12 = Pkcs12::builder()
+            .name("iloader")
+            .pkey(&key)
+            .cert(&cert)
+            .build2(password)
+            .unwrap();
+        (p12.to_der().unwrap(), cert.to_der().unwrap())
+    }
+
+    #[test]
+    fn loads_exported_identity() {
+        let (p12, cert_der) = exported_p12("secret");
+        let (serial, cert, _) = load_identity(&p12, "secret").unwrap();
+        assert_eq!(serial, "1ABC");
+        assert_eq!(cert.encode_der().unwrap(), cert_der);
+    }
+
+    #[test]
+    fn rejects_wrong_password() {
+        let (p12, _) = exported_p12("secret");
+        assert!(load_identity(&p12, "wrong").is_err());
+    }
+}
@@ -0,0 +1,289 @@
+use std::{
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use chrono::Timelike;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tauri::{AppHandle, Manager};
+use tauri_plugin_store::StoreExt;
+
+use crate::{
+    account::get_account,
+    device::{list_devices, DeviceInfo},
+    download::{download_with_progress, is_url},
+    inventory::{expiry_cutoff, load_inventory, InventoryEntry},
+    operation::{Operation, CANCELLED},
+    prepare::resolve_modifications,
+    sideload::{sideload, SideloadOptions},
+};
+
+// Set while a refresh pass is running so a manual trigger doesn't overlap the scheduler
+static REFRESHING: AtomicBool = AtomicBool::new(false);
+
+// Clears REFRESHING when the pass ends, also if it returns early or panics
+struct RefreshGuard;
+
+impl RefreshGuard {
+    fn acquire() -> Option<RefreshGuard> {
+        (!REFRESHING.swap(true, Ordering::SeqCst)).then_some(RefreshGuard)
+    }
+}
+
+impl Drop for RefreshGuard {
+    fn drop(&mut self) {
+        REFRESHING.store(false, Ordering::SeqCst);
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RefreshSettings {
+    pub enabled: bool,
+    // Refresh apps whose profile expires within this many hours
+    pub lead_time_hours: u64,
+    // Local hours (0-23) during which nothing is refreshed. The range may wrap past midnight.
+    pub quiet_hours_start: Option<u32>,
+    pub quiet_hours_end: Option<u32>,
+    // UDIDs of the devices that opted in
+    pub devices: Vec<String>,
+    pub check_interval_minutes: u64,
+}
+
+impl Default for RefreshSettings {
+    fn default() -> Self {
+        RefreshSettings {
+            enabled: false,
+            lead_time_hours: 24,
+            quiet_hours_start: None,
+            quiet_hours_end: None,
+            devices: vec![],
+            check_interval_minutes: 30,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RefreshResult {
+    pub udid: String,
+    pub bundle_id: String,
+    pub name: String,
+    pub error: Option<String>,
+}
+
+fn load_settings(handle: &AppHandle) -> Result<RefreshSettings, String> {
+    let store = handle
+        .store("data.json")
+        .map_err(|e| format!("Failed to get store: {:?}", e))?;
+    Ok(store
+        .get("refreshSettings")
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default())
+}
+
+fn in_quiet_hours(settings: &RefreshSettings, hour: u32) -> bool {
+    match (settings.quiet_hours_start, settings.quiet_hours_end) {
+        (Some(start), Some(end)) if start <= end => hour >= start && hour < end,
+        (Some(start), Some(end)) => hour >= start || hour < end,
+        _ => false,
+    }
+}
+
+fn is_due(entry: &InventoryEntry, settings: &RefreshSettings) -> bool {
+    let cutoff = expiry_cutoff(settings.lead_time_hours);
+    settings.devices.contains(&entry.udid)
+        && entry.profile_expiry.is_some_and(|expiry| expiry <= cutoff)
+}
+
+// Re-signs and reinstalls one inventory entry from its saved source, reporting through the
+// "refresh" operation.
+async fn refresh_app(
+    handle: &AppHandle,
+    device: DeviceInfo,
+    entry: &InventoryEntry,
+) -> Result<(), String> {
+    let window = handle
+        .get_webview_window("main")
+        .ok_or("Main window is not available".to_string())?;
+    let window = window.as_ref().window();
+    let op = Operation::new("refresh".to_string(), &window, None);
+    op.set_params(json!({
+        "bundleId": entry.bundle_id,
+        "udid": entry.udid,
+        "source": entry.source,
+    }));
+    op.start("download")?;
+    op.wait_for_account("download", Some(&device.uuid)).await?;
+    let apple_id = op.fail_if_err("download", op.account())?.apple_id.clone();
+    if !apple_id.eq_ignore_ascii_case(&entry.apple_id) {
+        return op.fail(
+            "download",
+            format!(
+                "{} was installed by {}, not {}",
+                entry.name, entry.apple_id, apple_id
+            ),
+        );
+    }
+
+    // URL sources are fetched again so the refresh picks up newer builds
+    let local_path = if is_url(&entry.source) {
+        op.fail_if_err(
+            "download",
+            download_with_progress(handle, &window, &entry.source, op.token()).await,
+        )?
+        .to_string_lossy()
+        .to_string()
+    } else if Path::new(&entry.source).exists() {
+        entry.source.clone()
+    } else {
+        return op.fail(
+            "download",
+            format!("The source of {} is no longer available", entry.name),
+        );
+    };
+
+    // Same overrides, stripped extensions, icon and entitlements as the original install, so the
+    // bundle ID and the App IDs it uses stay the same
+    let mut modifications = op.fail_if_err(
+        "download",
+        resolve_modifications(handle, &entry.source, None, None, None),
+    )?;
+    modifications.strip_extensions = entry.removed_extensions.clone();
+    modifications.inject = entry.injected.clone();
+    modifications.substrate = entry.substrate.clone();
+    modifications.icon = entry.icon.clone();
+    let options = SideloadOptions {
+        ignore_app_id_quota: entry.ignore_app_id_quota,
+        modifications,
+        entitlements: entry.entitlements.clone(),
+        source: Some(entry.source.clone()),
+        ..Default::default()
+    };
+    op.move_on("download", "login")?;
+
+    info!("Refreshing {} on {}", entry.bundle_id, entry.device_name);
+    let report = sideload(&op, handle.clone(), device, local_path, options).await?;
+    if report.bundle_id != entry.bundle_id {
+        op.warn(format!(
+            "Refresh of {} installed {} instead",
+            entry.bundle_id, report.bundle_id
+        ));
+    }
+    op.complete("install")?;
+    Ok(())
+}
+
+// Refreshes every due app on a connected, opted-in device. `force` ignores quiet hours.
+async fn refresh_due(
+    handle: &AppHandle,
+    settings: &RefreshSettings,
+    force: bool,
+) -> Result<Vec<RefreshResult>, String> {
+    if !force && in_quiet_hours(settings, chrono::Local::now().hour()) {
+        debug!("Within quiet hours, skipping refresh");
+        return Ok(vec![]);
+    }
+    let due: Vec<InventoryEntry> = load_inventory(handle)?
+        .into_iter()
+        .filter(|e| is_due(e, settings))
+        .collect();
+    if due.is_empty() {
+        return Ok(vec![]);
+    }
+
+    // Signing with another account would change the bundle ID, so only the account that
+    // installed an app refreshes it
+    let apple_id = match get_account() {
+        Ok(account) => account.apple_id.clone(),
+        Err(_) => {
+            debug!("Not logged in, skipping refresh of {} apps", due.len());
+            return Ok(vec![]);
+        }
+    };
+    let devices = list_devices().await?;
+
+    let Some(_guard) = RefreshGuard::acquire() else {
+        return Err("A refresh is already running".to_string());
+    };
+    let mut results = vec![];
+    for entry in due {
+        if !entry.apple_id.eq_ignore_ascii_case(&apple_id) {
+            debug!(
+                "{} was installed by {}, skipping",
+                entry.bundle_id, entry.apple_id
+            );
+            continue;
+        }
+        let Some(device) = devices.iter().find(|d| d.uuid == entry.udid) else {
+            debug!("{} is not connected, skipping", entry.device_name);
+            continue;
+        };
+        let res = refresh_app(handle, device.clone(), &entry).await;
+        if let Err(e) = &res {
+            warn!("Failed to refresh {}: {}", entry.bundle_id, e);
+        }
+        let cancelled = res.as_ref().is_err_and(|e| e == CANCELLED);
+        results.push(RefreshResult {
+            udid: entry.udid.clone(),
+            bundle_id: entry.bundle_id.clone(),
+            name: entry.name.clone(),
+            error: res.err(),
+        });
+        // Cancelling a refresh skips the apps after it too
+        if cancelled {
+            break;
+        }
+    }
+
+    Ok(results)
+}
+
+pub fn start_scheduler(handle: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let settings = load_settings(&handle).unwrap_or_default();
+            if settings.enabled {
+                if let Err(e) = refresh_due(&handle, &settings, false).await {
+                    warn!("Auto-refresh failed: {}", e);
+                }
+            }
+            let minutes = settings.check_interval_minutes.max(5);
+            tokio::time::sleep(Duration::from_secs(minutes * 60)).await;
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_refresh_settings(handle: AppHandle) -> Result<RefreshSettings, String> {
+    load_settings(&handle)
+}
+
+#[tauri::command]
+pub fn set_refresh_settings(handle: AppHandle, settings: RefreshSettings) -> Result<(), String> {
+    for hour in [settings.quiet_hours_start, settings.quiet_hours_end]
+        .into_iter()
+        .flatten()
+    {
+        if hour > 23 {
+            return Err(format!("Invalid quiet hour: {}", hour));
+        }
+    }
+    let store = handle
+        .store("data.json")
+        .map_err(|e| format!("Failed to get store: {:?}", e))?;
+    store.set(
+        "refreshSettings",
+        serde_json::to_value(&settings).map_err(|e| e.to_string())?,
+    );
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn refresh_now(handle: AppHandle) -> Result<Vec<RefreshResult>, String> {
+    let settings = load_settings(&handle)?;
+    refresh_due(&handle, &settings, true).await
+}
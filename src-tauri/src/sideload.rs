@@ -1,218 +1,626 @@
-use std::path::PathBuf;
+use std::path::Path;
 
 use crate::{
-    account::get_developer_session,
-    device::{get_provider, DeviceInfoMutex},
+    account::developer_session,
+    backup::backup_if_installed,
+    device::{DeviceInfo, DeviceInfoMutex},
+    download::{download_with_progress, is_url},
+    groups::{
+        allocate_groups, is_live_container, merge_profile_groups, update_alt_app_groups,
+        GroupRequest,
+    },
+    inject::InjectionReport,
+    install::{install_package, upload_package},
+    inventory::{now, record_install, InventoryEntry},
+    ipa::{
+        bundle_ids, parse_provisioning_profile, plist_string, predicted_app_ids, read_bundle_id,
+        read_info_plist, signer_serial,
+    },
+    ledger::{preflight, record_new},
     operation::Operation,
-    pairing::{get_sidestore_info, place_pairing},
+    pairing::{get_sidestore_info, place_pairing, PairingAppInfo, PairingTarget},
+    prepare::{
+        prepare_app, remember_modifications, resolve_modifications, AppModifications,
+        PreparedApp,
+    },
+    presets::{preset_for, EntitlementOverrides},
+    sign::write_ipa,
+    signing::{
+        assign_default_group, certificate, check_entitlements, download_profile, open_app,
+        register_app_ids, sign, store_dir,
+    },
 };
-use isideload::{sideload::sideload_app, SideloadConfiguration};
-use tauri::{AppHandle, Manager, State, Window};
+use isideload::developer_session::{
+    DeveloperDeviceType, DeveloperSession, DeveloperTeam, ListAppIdsResponse,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tauri::{AppHandle, State, Window};
+use tokio::sync::mpsc::unbounded_channel;
 use log::{error, warn, info, debug};
 
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SideloadReport {
+    pub bundle_id: String,
+    pub removed_extensions: Vec<String>,
+    pub app_ids_saved: usize,
+    pub injection: Option<InjectionReport>,
+}
+
+// Optional settings for sideload_operation as sent by the frontend
+#[derive(Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SideloadParams {
+    pub backup_before_install: bool,
+    pub bundle_id_override: Option<String>,
+    // Use the bundle ID override as is instead of appending the team ID
+    pub exact_bundle_id: Option<bool>,
+    pub display_name_override: Option<String>,
+    pub strip_all_extensions: bool,
+    pub strip_extensions: Vec<String>,
+    pub ignore_app_id_quota: bool,
+    pub inject: Vec<String>,
+    pub substrate_path: Option<String>,
+    pub icon_path: Option<String>,
+    pub entitlements: EntitlementOverrides,
+}
+
+impl SideloadParams {
+    pub fn into_options(
+        self,
+        handle: &AppHandle,
+        app_path: &str,
+    ) -> Result<SideloadOptions, String> {
+        let mut modifications = resolve_modifications(
+            handle,
+            app_path,
+            self.bundle_id_override,
+            self.exact_bundle_id,
+            self.display_name_override,
+        )?;
+        modifications.strip_all_extensions = self.strip_all_extensions;
+        modifications.strip_extensions = self.strip_extensions;
+        modifications.inject = self.inject;
+        modifications.substrate = self.substrate_path;
+        modifications.icon = self.icon_path;
+        Ok(SideloadOptions {
+            backup_before_install: self.backup_before_install,
+            ignore_app_id_quota: self.ignore_app_id_quota,
+            modifications,
+            entitlements: self.entitlements,
+            source: None,
+            remember_as: Some(app_path.to_string()),
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct SideloadOptions {
+    pub backup_before_install: bool,
+    pub ignore_app_id_quota: bool,
+    pub modifications: AppModifications,
+    pub entitlements: EntitlementOverrides,
+    // Where the IPA came from, recorded in the inventory. Defaults to the app path.
+    pub source: Option<String>,
+    // Where the bundle ID and name overrides are saved once the app is installed
+    pub remember_as: Option<String>,
+}
+
+impl SideloadOptions {
+    // The modifications and entitlement overrides with the signing preset for the app at
+    // `app_path` filled in, where one is saved
+    pub fn with_preset(
+        &self,
+        handle: &AppHandle,
+        app_path: &str,
+    ) -> Result<(AppModifications, EntitlementOverrides), String> {
+        let mut modifications = self.modifications.clone();
+        let mut overrides = self.entitlements.clone();
+        if let Some(preset) = preset_for(handle, &read_bundle_id(Path::new(app_path))?)? {
+            info!("Applying signing preset for {}", preset.bundle_id);
+            preset.apply(&mut modifications);
+            for (key, value) in preset.entitlements.add {
+                overrides.add.entry(key).or_insert(value);
+            }
+            overrides.remove.extend(preset.entitlements.remove);
+        }
+        Ok((modifications, overrides))
+    }
+}
+
+// Everything the signing steps need, set up the same way for installing and exporting
+pub struct SigningContext {
+    pub dev_session: DeveloperSession,
+    pub team: DeveloperTeam,
+    pub apple_id: String,
+    pub prepared: PreparedApp,
+    // The main app is registered as "<bundle id>.<team id>" like isideload does, mirror that so
+    // callers know which install is ours
+    pub installed_bundle_id: String,
+    pub app_ids_before: ListAppIdsResponse,
+    // Entitlements signed in on top of or removed from the ones in the provisioning profile
+    pub overrides: EntitlementOverrides,
+}
+
+// Logs in, prepares the app and checks the App ID quota before anything is registered. Goes
+// through the "login", "team" and "prepare" steps; "login" must already be started.
+pub async fn begin_signing(
+    op: &Operation<'_>,
+    handle: &AppHandle,
+    app_path: &str,
+    options: &SideloadOptions,
+) -> Result<SigningContext, String> {
+    info!("Getting developer session for sideload");
+    let account = op.fail_if_err("login", op.account())?;
+    let apple_id = account.apple_id.clone();
+    let dev_session = op.fail_if_err(
+        "login",
+        developer_session(account).await.map_err(|e| {
+            error!("Failed to get developer session: {}", e);
+            e.to_string()
+        }),
+    )?;
+
+    op.move_on("login", "team")?;
+    let team = op.fail_if_err(
+        "team",
+        dev_session
+            .get_team()
+            .await
+            .map_err(|e| format!("Failed to get developer team: {:?}", e)),
+    )?;
+
+    op.move_on("team", "prepare")?;
+    op.fail_if_err(
+        "prepare",
+        prepare_signing(handle, app_path, options, dev_session, team, apple_id).await,
+    )
+}
+
+async fn prepare_signing(
+    handle: &AppHandle,
+    app_path: &str,
+    options: &SideloadOptions,
+    dev_session: DeveloperSession,
+    team: DeveloperTeam,
+    apple_id: String,
+) -> Result<SigningContext, String> {
+    let (modifications, overrides) = options.with_preset(handle, app_path)?;
+    let prepared = prepare_app(handle, Path::new(app_path), &modifications)?;
+    let installed_bundle_id = prepared.installed_bundle_id(&team.team_id);
+
+    let app_ids_before = dev_session
+        .list_app_ids(DeveloperDeviceType::Ios, &team)
+        .await
+        .map_err(|e| format!("Failed to list App IDs: {:?}", e))?;
+    let (bundle_id, extension_ids) = bundle_ids(&prepared.path)?;
+    let needed = predicted_app_ids(&bundle_id, &extension_ids, &installed_bundle_id);
+    match preflight(handle, &apple_id, &team.team_id, &needed, &app_ids_before) {
+        Ok(to_register) => debug!("Will register {} new App IDs", to_register.len()),
+        Err(e) if options.ignore_app_id_quota => warn!("Ignoring App ID quota: {}", e),
+        Err(e) => return Err(e),
+    }
+
+    Ok(SigningContext {
+        dev_session,
+        team,
+        apple_id,
+        prepared,
+        installed_bundle_id,
+        app_ids_before,
+        overrides,
+    })
+}
+
+// Registers the certificate, device, App IDs and profile and signs the prepared app for `udid`.
+// Follows "prepare" and leaves "sign" started. App IDs registered along the way are recorded in
+// the ledger even if signing fails afterwards, since they count against the quota either way.
+pub async fn sign_prepared(
+    op: &Operation<'_>,
+    handle: &AppHandle,
+    ctx: &SigningContext,
+    udid: &str,
+    device_name: &str,
+) -> Result<(), String> {
+    let res = run_signing(op, handle, ctx, udid, device_name).await;
+    finish_signing(handle, ctx).await;
+    res
+}
+
+async fn run_signing(
+    op: &Operation<'_>,
+    handle: &AppHandle,
+    ctx: &SigningContext,
+    udid: &str,
+    device_name: &str,
+) -> Result<(), String> {
+    let store_dir = op.fail_if_err("prepare", store_dir(handle))?;
+
+    op.move_on("prepare", "certificate")?;
+    let cert = op.fail_if_err(
+        "certificate",
+        certificate(
+            &store_dir,
+            &ctx.dev_session,
+            &ctx.team,
+            &ctx.apple_id,
+            udid,
+            device_name,
+        )
+        .await,
+    )?;
+
+    op.move_on("certificate", "app_ids")?;
+    let mut app = op.fail_if_err("app_ids", open_app(&ctx.prepared.path))?;
+    let app_ids = op.fail_if_err(
+        "app_ids",
+        register_app_ids(
+            &ctx.dev_session,
+            &ctx.team,
+            &mut app,
+            &ctx.installed_bundle_id,
+        )
+        .await,
+    )?;
+    op.fail_if_err(
+        "app_ids",
+        assign_default_group(
+            &ctx.dev_session,
+            &ctx.team,
+            &mut app,
+            &ctx.prepared.bundle_id,
+            &app_ids,
+            &cert,
+        )
+        .await,
+    )?;
+    let mut extra = op.fail_if_err("app_ids", ctx.overrides.to_plist())?;
+    let live_container = is_live_container(&ctx.prepared.original_bundle_id, &ctx.prepared.path);
+    let mut requests = if live_container {
+        let bundle_dirs = std::iter::once(&app.bundle)
+            .chain(app.bundle.app_extensions())
+            .map(|b| b.bundle_dir.clone());
+        op.fail_if_err(
+            "app_ids",
+            bundle_dirs
+                .zip(app_ids.iter().cloned())
+                .map(|(dir, app_id)| GroupRequest::from_bundle(&dir, app_id))
+                .collect::<Result<Vec<_>, String>>(),
+        )?
+    } else {
+        vec![GroupRequest {
+            app_id: app_ids[0].clone(),
+            keychain_groups: vec![],
+            app_groups: vec![],
+        }]
+    };
+    // Groups from the overrides are the main app's
+    requests[0].take_from(&mut extra);
+    let groups = op.fail_if_err(
+        "app_ids",
+        allocate_groups(&ctx.dev_session, &ctx.team, &requests).await,
+    )?;
+    if let Some(groups) = groups {
+        update_alt_app_groups(&mut app.bundle.app_info, &groups);
+        for (key, value) in groups {
+            extra.insert(key, value);
+        }
+    }
+
+    op.move_on("app_ids", "profile")?;
+    let profile_path = ctx.prepared.profile_path();
+    op.fail_if_err(
+        "profile",
+        download_profile(&ctx.dev_session, &ctx.team, &app_ids[0], &profile_path).await,
+    )?;
+
+    op.move_on("profile", "sign")?;
+    let entitlements = if extra.is_empty() && ctx.overrides.remove.is_empty() {
+        None
+    } else {
+        Some(op.fail_if_err("sign", write_entitlements(ctx, &profile_path, extra))?)
+    };
+    op.fail_if_err("sign", sign(&app, &cert, &profile_path, entitlements).await)
+}
+
+// Writes the profile's entitlements with the overrides applied for zsign to sign with
+fn write_entitlements(
+    ctx: &SigningContext,
+    profile_path: &Path,
+    mut extra: plist::Dictionary,
+) -> Result<std::path::PathBuf, String> {
+    let profile = std::fs::read(profile_path)
+        .ok()
+        .and_then(|data| parse_provisioning_profile(&data))
+        .ok_or("Failed to read the downloaded provisioning profile".to_string())?;
+    let mut entitlements = profile.entitlements.unwrap_or_default();
+    check_entitlements(&entitlements, &extra)?;
+    merge_profile_groups(&entitlements, &mut extra);
+    for (key, value) in extra {
+        entitlements.insert(key, value);
+    }
+    for key in &ctx.overrides.remove {
+        entitlements.remove(key);
+    }
+
+    let path = ctx.prepared.entitlements_path();
+    plist::Value::Dictionary(entitlements)
+        .to_file_xml(&path)
+        .map_err(|e| format!("Failed to write entitlements: {}", e))?;
+    Ok(path)
+}
+
+// Records the App IDs registered while signing in the ledger
+async fn finish_signing(handle: &AppHandle, ctx: &SigningContext) {
+    match ctx
+        .dev_session
+        .list_app_ids(DeveloperDeviceType::Ios, &ctx.team)
+        .await
+    {
+        Ok(app_ids_after) => record_new(
+            handle,
+            &ctx.apple_id,
+            &ctx.team.team_id,
+            &ctx.app_ids_before,
+            &app_ids_after,
+        ),
+        Err(e) => warn!("Failed to list App IDs after signing: {:?}", e),
+    }
+}
+
+// Signs the app and installs it on `device`, going from "login" through the signing steps,
+// "upload" and "install". "login" must already be started and "install" is left started.
 pub async fn sideload(
+    op: &Operation<'_>,
     handle: AppHandle,
-    device_state: State<'_, DeviceInfoMutex>,
+    device: DeviceInfo,
     app_path: String,
-) -> Result<(), String> {
+    options: SideloadOptions,
+) -> Result<SideloadReport, String> {
     info!("Starting sideload operation for: {}", app_path);
+    info!("Using device: {} (ID: {})", device.name, device.id);
 
-    let device = {
-        let device_lock = device_state.lock().unwrap();
-        match &*device_lock {
-            Some(d) => {
-                info!("Using device: {} (ID: {})", d.name, d.id);
-                d.clone()
-            },
-            None => {
-                error!("No device selected for sideload");
-                return Err("No device selected".to_string());
+    let ctx = begin_signing(op, &handle, &app_path, &options).await?;
+    sign_prepared(op, &handle, &ctx, &device.uuid, &device.name).await?;
+    let prepared = &ctx.prepared;
+    let installed_bundle_id = ctx.installed_bundle_id.clone();
+
+    op.move_on("sign", "upload")?;
+    let package = prepared.package_path();
+    op.fail_if_err("upload", write_ipa(&prepared.path, &package))?;
+    let remote_path = op.fail_if_err(
+        "upload",
+        upload_package(&device, &package, |percent| {
+            let _ = op.progress("upload", percent);
+        })
+        .await,
+    )?;
+
+    op.move_on("upload", "install")?;
+    if options.backup_before_install {
+        // The app may also have been installed under its original ID by something else
+        let candidates = vec![installed_bundle_id.clone(), prepared.bundle_id.clone()];
+        match backup_if_installed(&handle, &device, &candidates).await {
+            Ok(Some(path)) => info!("Backed up existing app data to {:?}", path),
+            Ok(None) => debug!("App is not installed yet, nothing to back up"),
+            Err(e) => {
+                error!("Failed to back up app data: {}", e);
+                return op.fail(
+                    "install",
+                    format!("Failed to back up existing app data: {}", e),
+                );
             }
         }
-    };
+    }
 
-    debug!("Getting device provider");
-    let provider = get_provider(&device).await.map_err(|e| {
-        error!("Failed to get device provider: {}", e);
-        e
-    })?;
-
-    let app_data_dir = handle
-        .path()
-        .app_data_dir()
-        .map_err(|e| {
-            error!("Failed to get app data dir: {:?}", e);
-            format!("Failed to get app data dir: {:?}", e)
-        })?;
-
-    debug!("Setting up sideload configuration");
-    let config = SideloadConfiguration::default()
-        .set_machine_name("iloader".to_string())
-        .set_store_dir(app_data_dir);
+    info!("Installing {}", installed_bundle_id);
+    let (tx, rx) = unbounded_channel();
+    op.fail_if_err(
+        "install",
+        op.track(
+            "install",
+            rx,
+            install_package(&device, &remote_path, move |percent| {
+                let _ = tx.send(percent);
+            }),
+        )
+        .await,
+    )?;
+    if let Some(source) = &options.remember_as {
+        if let Err(e) = remember_modifications(&handle, source, &options.modifications) {
+            warn!("Failed to save the overrides of {}: {}", source, e);
+        }
+    }
 
-    info!("Getting developer session for sideload");
-    let dev_session = get_developer_session().await.map_err(|e| {
-        error!("Failed to get developer session: {}", e);
-        e.to_string()
-    })?;
+    let info = read_info_plist(&prepared.path).unwrap_or_default();
+    let profile = std::fs::read(prepared.path.join("embedded.mobileprovision"))
+        .ok()
+        .and_then(|data| parse_provisioning_profile(&data));
+    if profile.is_none() {
+        warn!("Could not read the provisioning profile of the signed app");
+    }
+    let certificate_serial = signer_serial(&prepared.path).unwrap_or_else(|e| {
+        warn!(
+            "Could not read the signing certificate of the signed app: {}",
+            e
+        );
+        None
+    });
+    let entry = InventoryEntry {
+        udid: device.uuid.clone(),
+        device_name: device.name.clone(),
+        bundle_id: installed_bundle_id.clone(),
+        original_bundle_id: prepared.original_bundle_id.clone(),
+        name: plist_string(&info, "CFBundleDisplayName")
+            .or_else(|| plist_string(&info, "CFBundleName"))
+            .unwrap_or_else(|| prepared.bundle_id.clone()),
+        version: plist_string(&info, "CFBundleShortVersionString"),
+        build: plist_string(&info, "CFBundleVersion"),
+        apple_id: ctx.apple_id.clone(),
+        team_id: ctx.team.team_id.clone(),
+        team_name: profile.as_ref().and_then(|p| p.team_name.clone()),
+        certificate_serial,
+        profile_expiry: profile.as_ref().and_then(|p| p.expiration),
+        installed_at: now(),
+        source: options.source.clone().unwrap_or_else(|| app_path.clone()),
+        removed_extensions: prepared.removed_extensions.clone(),
+        injected: options.modifications.inject.clone(),
+        substrate: options.modifications.substrate.clone(),
+        icon: options.modifications.icon.clone(),
+        entitlements: options.entitlements.clone(),
+        ignore_app_id_quota: options.ignore_app_id_quota,
+    };
+    if let Err(e) = record_install(&handle, entry) {
+        warn!("Failed to record install in inventory: {}", e);
+    }
 
-    info!("Starting sideload_app operation");
-    sideload_app(&provider, &dev_session, app_path.into(), config)
-        .await
-        .map_err(|e| {
-            let error_str = format!("{:?}", e);
-            error!("Sideload failed: {}", error_str);
-
-            match e {
-                isideload::Error::Certificate(s) if s == "You have too many certificates!" => {
-                    warn!("Too many certificates error");
-                    "You have too many certificates. Revoke one by clicking \"Certificates\" and \"Revoke\".".to_string()
-                }
-                _ => {
-                    // Обработка ошибок парсинга machineId
-                    if error_str.contains("machineId") || error_str.contains("Parse") || error_str.contains("machineld") {
-                        warn!("machineId parsing error during sideload");
-                        format!(
-                            "Failed to parse certificate data from Apple API (machineId parsing error).\n\n\
-                            This is a known issue that may occur due to changes in Apple's API format.\n\n\
-                            Possible solutions:\n\
-                            1. Try logging out and logging back in\n\
-                            2. Revoke all existing certificates and create new ones\n\
-                            3. Check for updates to iloader\n\
-                            4. Report this issue to the iloader developers\n\n\
-                            Technical details: {}", error_str
-                        )
-                    } else {
-                        error_str
-                    }
-                }
-            }
-        })?;
+    info!("Sideload operation completed successfully ({})", installed_bundle_id);
+    if !prepared.removed_extensions.is_empty() {
+        info!(
+            "Removed {} extensions: {:?}",
+            prepared.removed_extensions.len(),
+            prepared.removed_extensions
+        );
+    }
+    Ok(SideloadReport {
+        bundle_id: installed_bundle_id,
+        app_ids_saved: prepared.removed_extensions.len(),
+        removed_extensions: prepared.removed_extensions.clone(),
+        injection: prepared.injection.clone(),
+    })
+}
 
-    info!("Sideload operation completed successfully");
-    Ok(())
+// Runs the "download" step and the sideload steps shared by operations that sideload a local
+// path or URL. The "download" step must already be started and "install" is left started.
+pub async fn download_and_sideload(
+    op: &Operation<'_>,
+    handle: AppHandle,
+    window: &Window,
+    device: DeviceInfo,
+    app_path: String,
+    params: SideloadParams,
+) -> Result<SideloadReport, String> {
+    // Overrides are keyed by the URL rather than the cached file so they survive new builds
+    let mut options = op.fail_if_err("download", params.into_options(&handle, &app_path))?;
+    let local_path = if is_url(&app_path) {
+        let path = op.fail_if_err(
+            "download",
+            download_with_progress(&handle, window, &app_path, op.token()).await,
+        )?;
+        options.source = Some(app_path.clone());
+        path.to_string_lossy().to_string()
+    } else {
+        app_path
+    };
+    op.move_on("download", "login")?;
+    let report = sideload(op, handle, device, local_path, options).await?;
+    if let Some(injection) = &report.injection {
+        for warning in &injection.warnings {
+            op.warn(warning.clone());
+        }
+    }
+    Ok(report)
 }
 
 #[tauri::command]
 pub async fn sideload_operation(
     handle: AppHandle,
     window: Window,
+    run_id: Option<u64>,
     device_state: State<'_, DeviceInfoMutex>,
     app_path: String,
-) -> Result<(), String> {
-    let op = Operation::new("sideload".to_string(), &window);
-    op.start("install")?;
-    op.fail_if_err("install", sideload(handle, device_state, app_path).await)?;
+    options: Option<SideloadParams>,
+) -> Result<SideloadReport, String> {
+    let params = options.unwrap_or_default();
+    let op = Operation::new("sideload".to_string(), &window, run_id);
+    op.set_params(json!({ "appPath": app_path, "options": params }));
+    op.start("download")?;
+    let device = {
+        let device_guard = device_state.lock().unwrap();
+        match &*device_guard {
+            Some(d) => d.clone(),
+            None => return op.fail("download", "No device selected".to_string()),
+        }
+    };
+    op.wait_for_account("download", Some(&device.uuid)).await?;
+    let report = download_and_sideload(&op, handle, &window, device, app_path, params).await?;
     op.complete("install")?;
-    Ok(())
+    Ok(report)
 }
 
+// Returns the SideStore installs to choose from when the pairing file couldn't be placed in the
+// new one, or nothing when it was
 #[tauri::command]
 pub async fn install_sidestore_operation(
     handle: AppHandle,
     window: Window,
+    run_id: Option<u64>,
     device_state: State<'_, DeviceInfoMutex>,
     nightly: bool,
     live_container: bool,
-) -> Result<(), String> {
-    let op = Operation::new("install_sidestore".to_string(), &window);
+) -> Result<Vec<PairingAppInfo>, String> {
+    let op = Operation::new("install_sidestore".to_string(), &window, run_id);
+    op.set_params(json!({ "nightly": nightly, "liveContainer": live_container }));
     op.start("download")?;
-    // TODO: Cache & check version to avoid re-downloading
-    let (filename, url) = if live_container {
+    let url = if live_container {
         if nightly {
-            ("LiveContainerSideStore-Nightly.ipa", "https://github.com/LiveContainer/LiveContainer/releases/download/nightly/LiveContainer+SideStore.ipa")
+            "https://github.com/LiveContainer/LiveContainer/releases/download/nightly/LiveContainer+SideStore.ipa"
         } else {
-            ("LiveContainerSideStore.ipa", "https://github.com/LiveContainer/LiveContainer/releases/latest/download/LiveContainer+SideStore.ipa")
+            "https://github.com/LiveContainer/LiveContainer/releases/latest/download/LiveContainer+SideStore.ipa"
         }
     } else if nightly {
-        (
-            "SideStore-Nightly.ipa",
-            "https://github.com/SideStore/SideStore/releases/download/nightly/SideStore.ipa",
-        )
+        "https://github.com/SideStore/SideStore/releases/download/nightly/SideStore.ipa"
     } else {
-        (
-            "SideStore.ipa",
-            "https://github.com/SideStore/SideStore/releases/latest/download/SideStore.ipa",
-        )
+        "https://github.com/SideStore/SideStore/releases/latest/download/SideStore.ipa"
     };
 
-    let dest = handle
-        .path()
-        .temp_dir()
-        .map_err(|e| format!("Failed to get temp dir: {:?}", e))?
-        .join(filename);
-    op.fail_if_err("download", download(url, &dest).await)?;
-    op.move_on("download", "install")?;
+    let dest = op.fail_if_err(
+        "download",
+        download_with_progress(&handle, &window, url, op.token()).await,
+    )?;
+    op.move_on("download", "login")?;
     let device = {
         let device_guard = device_state.lock().unwrap();
         match &*device_guard {
             Some(d) => d.clone(),
-            None => return op.fail("install", "No device selected".to_string()),
+            None => return op.fail("login", "No device selected".to_string()),
         }
     };
-    op.fail_if_err(
-        "install",
-        sideload(handle, device_state, dest.to_string_lossy().to_string()).await,
-    )?;
+    op.wait_for_account("login", Some(&device.uuid)).await?;
+    let report = sideload(
+        &op,
+        handle,
+        device.clone(),
+        dest.to_string_lossy().to_string(),
+        SideloadOptions {
+            source: Some(url.to_string()),
+            ..Default::default()
+        },
+    )
+    .await?;
     op.move_on("install", "pairing")?;
-    let sidestore_info = op.fail_if_err(
+    let target = op.fail_if_err(
         "pairing",
-        get_sidestore_info(device.clone(), live_container).await,
+        get_sidestore_info(device.clone(), live_container, &report.bundle_id).await,
     )?;
-    if let Some(info) = sidestore_info {
-        op.fail_if_err(
-            "pairing",
-            place_pairing(device, info.bundle_id, info.path).await,
-        )?;
-    } else {
-        return op.fail(
-            "pairing",
-            "Could not find SideStore's bundle ID".to_string(),
-        );
-    }
+    let candidates = match target {
+        PairingTarget::Installed(info) => {
+            op.fail_if_err(
+                "pairing",
+                place_pairing(device, info.bundle_id, info.path).await,
+            )?;
+            vec![]
+        }
+        PairingTarget::Candidates(candidates) => {
+            op.warn(format!(
+                "{} was not found after installing, the pairing file was not placed",
+                report.bundle_id
+            ));
+            candidates
+        }
+    };
 
     op.complete("pairing")?;
-    Ok(())
-}
-
-pub async fn download(url: impl AsRef<str>, dest: &PathBuf) -> Result<(), String> {
-    let url_str = url.as_ref();
-    info!("Downloading file from: {}", url_str);
-    info!("Destination: {:?}", dest);
-
-    let response = reqwest::get(url_str)
-        .await
-        .map_err(|e| {
-            error!("Failed to start download: {}", e);
-            e.to_string()
-        })?;
-
-    if !response.status().is_success() {
-        error!("Download failed with HTTP status: {}", response.status());
-        return Err(format!(
-            "Failed to download file: HTTP {}",
-            response.status()
-        ));
-    }
-
-    let content_length = response.content_length();
-    if let Some(len) = content_length {
-        info!("Downloading {} bytes", len);
-    }
-
-    let bytes = response.bytes().await.map_err(|e| {
-        error!("Failed to read download response: {}", e);
-        e.to_string()
-    })?;
-
-    info!("Writing {} bytes to file", bytes.len());
-    tokio::fs::write(dest, &bytes)
-        .await
-        .map_err(|e| {
-            error!("Failed to write file: {}", e);
-            e.to_string()
-        })?;
-
-    info!("Download completed successfully");
-    Ok(())
+    Ok(candidates)
 }
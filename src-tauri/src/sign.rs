@@ -0,0 +1,158 @@
+use std::{fs::File, io::Write, path::Path};
+
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::json;
+use tauri::{AppHandle, Manager, State, Window};
+use zip::{write::SimpleFileOptions, ZipWriter};
+
+use crate::{
+    device::DeviceInfoMutex,
+    files::walk_local,
+    inject::InjectionReport,
+    ipa::{macho_slices, parse_provisioning_profile},
+    operation::Operation,
+    sideload::{begin_signing, sign_prepared, SideloadParams},
+};
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignReport {
+    pub output_path: String,
+    pub bundle_id: String,
+    pub udid: String,
+    pub profile_expiry: Option<u64>,
+    pub removed_extensions: Vec<String>,
+    pub injection: Option<InjectionReport>,
+}
+
+// Packs a signed .app into an IPA at `dest`. Zip doesn't carry the permissions of the bundle's
+// files, so Mach-O binaries are marked executable and everything else gets 0644.
+pub fn write_ipa(app_dir: &Path, dest: &Path) -> Result<(), String> {
+    let app_name = app_dir
+        .file_name()
+        .ok_or("Invalid app path".to_string())?
+        .to_string_lossy()
+        .to_string();
+    let prefix = format!("Payload/{}", app_name);
+    let (dirs, files) = walk_local(app_dir)?;
+
+    if let Some(parent) = dest.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create output dir: {}", e))?;
+    }
+    let file = File::create(dest).map_err(|e| format!("Failed to create IPA: {}", e))?;
+    let mut zip = ZipWriter::new(file);
+    let executable = SimpleFileOptions::default().unix_permissions(0o755);
+    let regular = SimpleFileOptions::default().unix_permissions(0o644);
+
+    zip.add_directory(&prefix, executable)
+        .map_err(|e| format!("Failed to write IPA: {}", e))?;
+    for dir in dirs {
+        zip.add_directory(format!("{}/{}", prefix, dir), executable)
+            .map_err(|e| format!("Failed to write IPA: {}", e))?;
+    }
+    for (local, rel) in files {
+        let contents =
+            std::fs::read(&local).map_err(|e| format!("Failed to read {:?}: {}", local, e))?;
+        let options = if macho_slices(&contents).is_empty() {
+            regular
+        } else {
+            executable
+        };
+        zip.start_file(format!("{}/{}", prefix, rel), options)
+            .map_err(|e| format!("Failed to write IPA: {}", e))?;
+        zip.write_all(&contents)
+            .map_err(|e| format!("Failed to write IPA: {}", e))?;
+    }
+
+    zip.finish()
+        .map_err(|e| format!("Failed to finish IPA: {}", e))?;
+    Ok(())
+}
+
+// Registers the device, App IDs and profile for `udid` and signs the app, without needing the
+// device to be connected. Goes from "login" through the signing steps to "package", which is
+// left started.
+pub async fn sign_to_file(
+    op: &Operation<'_>,
+    handle: &AppHandle,
+    app_path: &str,
+    udid: &str,
+    device_name: &str,
+    output_path: &Path,
+    params: SideloadParams,
+) -> Result<SignReport, String> {
+    info!("Signing {} for {} into {:?}", app_path, udid, output_path);
+    let options = op.fail_if_err("login", params.into_options(handle, app_path))?;
+    let ctx = begin_signing(op, handle, app_path, &options).await?;
+    sign_prepared(op, handle, &ctx, udid, device_name).await?;
+
+    let profile = std::fs::read(ctx.prepared.path.join("embedded.mobileprovision"))
+        .ok()
+        .and_then(|data| parse_provisioning_profile(&data));
+    if profile.is_none() {
+        warn!("Could not read the provisioning profile of the signed app");
+    }
+
+    op.move_on("sign", "package")?;
+    op.fail_if_err("package", write_ipa(&ctx.prepared.path, output_path))?;
+    info!("Wrote signed IPA to {:?}", output_path);
+
+    Ok(SignReport {
+        output_path: output_path.to_string_lossy().to_string(),
+        bundle_id: ctx.installed_bundle_id.clone(),
+        udid: udid.to_string(),
+        profile_expiry: profile.and_then(|p| p.expiration),
+        removed_extensions: ctx.prepared.removed_extensions.clone(),
+        injection: ctx.prepared.injection.clone(),
+    })
+}
+
+#[tauri::command]
+pub async fn sign_ipa_operation(
+    window: Window,
+    run_id: Option<u64>,
+    device_state: State<'_, DeviceInfoMutex>,
+    app_path: String,
+    output_path: String,
+    udid: Option<String>,
+    options: Option<SideloadParams>,
+) -> Result<SignReport, String> {
+    let op = Operation::new("sign_ipa".to_string(), &window, run_id);
+    let handle = window.app_handle().clone();
+    op.set_params(json!({
+        "appPath": app_path,
+        "outputPath": output_path,
+        "udid": udid,
+        "options": options,
+    }));
+    op.start("login")?;
+    // Falls back to the selected device so a connected phone doesn't need its UDID typed in. A
+    // typed in UDID is also used as the name if the device has to be registered.
+    let selected = device_state.lock().unwrap().clone();
+    let (udid, device_name) = match (udid.filter(|u| !u.trim().is_empty()), selected) {
+        (Some(udid), Some(d)) if d.uuid == udid.trim() => (d.uuid, d.name),
+        (Some(udid), _) => (udid.trim().to_string(), udid.trim().to_string()),
+        (None, Some(d)) => (d.uuid, d.name),
+        (None, None) => return op.fail("login", "No device UDID given".to_string()),
+    };
+    op.wait_for_account("login", None).await?;
+    let report = sign_to_file(
+        &op,
+        &handle,
+        &app_path,
+        &udid,
+        &device_name,
+        Path::new(&output_path),
+        options.unwrap_or_default(),
+    )
+    .await?;
+    if let Some(injection) = &report.injection {
+        for warning in &injection.warnings {
+            op.warn(warning.clone());
+        }
+    }
+    op.complete("package")?;
+    Ok(report)
+}
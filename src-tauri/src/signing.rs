@@ -0,0 +1,373 @@
+// The parts of isideload's sideload_app that come before installing, split up so each one runs
+// as its own operation step and iloader can sign in entitlements of its own. The behaviour
+// otherwise matches isideload 0.1.22.
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use isideload::{
+    application::Application,
+    certificate::CertificateIdentity,
+    developer_session::{AppId, DeveloperDeviceType, DeveloperSession, DeveloperTeam},
+    sideload::ensure_device_registered,
+    Error, SideloadLogger,
+};
+use log::{debug, error, info, warn};
+use tauri::{AppHandle, Manager};
+use zsign_rust::ZSignOptions;
+
+// isideload only reuses certificates created under this machine name
+pub const MACHINE_NAME: &str = "iloader";
+// The App ID feature that allows app groups to be assigned
+const APP_GROUP_FEATURE: &str = "APG3427HIY";
+const SIDESTORE_BUNDLE_ID: &str = "com.SideStore.SideStore";
+
+struct LogLogger;
+
+impl SideloadLogger for LogLogger {
+    fn log(&self, message: &str) {
+        info!("{}", message);
+    }
+
+    fn error(&self, error: &Error) {
+        error!("{}", error);
+    }
+}
+
+pub fn describe_error(e: Error) -> String {
+    let error_str = format!("{:?}", e);
+    error!("Sideload failed: {}", error_str);
+
+    match e {
+        Error::Certificate(s) if s == "You have too many certificates!" => {
+            warn!("Too many certificates error");
+            "You have too many certificates. Revoke one by clicking \"Certificates\" and \"Revoke\", or import the signing identity exported from another computer.".to_string()
+        }
+        _ => {
+            // Обработка ошибок парсинга machineId
+            if error_str.contains("machineId")
+                || error_str.contains("Parse")
+                || error_str.contains("machineld")
+            {
+                warn!("machineId parsing error during sideload");
+                format!(
+                    "Failed to parse certificate data from Apple API (machineId parsing error).\n\n\
+                    This is a known issue that may occur due to changes in Apple's API format.\n\n\
+                    Possible solutions:\n\
+                    1. Try logging out and logging back in\n\
+                    2. Revoke all existing certificates and create new ones\n\
+                    3. Check for updates to iloader\n\
+                    4. Report this issue to the iloader developers\n\n\
+                    Technical details: {}", error_str
+                )
+            } else {
+                error_str
+            }
+        }
+    }
+}
+
+// The directory isideload keeps each Apple ID's signing key and certificate in, see
+// identity::identity_dir
+pub fn store_dir(handle: &AppHandle) -> Result<PathBuf, String> {
+    handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data dir: {:?}", e))
+}
+
+// Registers the device and gets the account's iloader certificate, creating one if needed
+pub async fn certificate(
+    store_dir: &Path,
+    dev_session: &DeveloperSession,
+    team: &DeveloperTeam,
+    apple_id: &str,
+    udid: &str,
+    device_name: &str,
+) -> Result<CertificateIdentity, String> {
+    ensure_device_registered(&LogLogger, dev_session, team, udid, device_name)
+        .await
+        .map_err(describe_error)?;
+    CertificateIdentity::new(
+        store_dir,
+        dev_session,
+        apple_id.to_string(),
+        MACHINE_NAME.to_string(),
+    )
+    .await
+    .map_err(describe_error)
+}
+
+pub fn open_app(app_dir: &Path) -> Result<Application, String> {
+    Application::new(app_dir.to_path_buf()).map_err(describe_error)
+}
+
+// Renames the app to `main_id` and its extensions to match, registers the App IDs that don't
+// exist yet and enables app groups on all of them. Returns the App IDs, the main app's first.
+pub async fn register_app_ids(
+    dev_session: &DeveloperSession,
+    team: &DeveloperTeam,
+    app: &mut Application,
+    main_id: &str,
+) -> Result<Vec<AppId>, String> {
+    let original_id = app
+        .bundle
+        .bundle_identifier()
+        .ok_or("No bundle identifier found in the app".to_string())?
+        .to_string();
+    for ext in app.bundle.app_extensions_mut() {
+        let Some(id) = ext.bundle_identifier().map(|id| id.to_string()) else {
+            continue;
+        };
+        match id.strip_prefix(&original_id) {
+            Some(suffix) if !suffix.is_empty() => {
+                ext.set_bundle_identifier(&format!("{}{}", main_id, suffix))
+            }
+            _ => {
+                return Err(format!(
+                    "Extension {} is not part of the main app bundle identifier: {}",
+                    ext.bundle_name().unwrap_or("Unknown"),
+                    id
+                ))
+            }
+        }
+    }
+    app.bundle.set_bundle_identifier(main_id);
+
+    let bundles: Vec<(String, String)> = std::iter::once(&app.bundle)
+        .chain(app.bundle.app_extensions())
+        .map(|b| {
+            (
+                b.bundle_identifier().unwrap_or_default().to_string(),
+                b.bundle_name().unwrap_or_default().to_string(),
+            )
+        })
+        .collect();
+    let existing = dev_session
+        .list_app_ids(DeveloperDeviceType::Ios, team)
+        .await
+        .map_err(describe_error)?;
+    for (id, name) in &bundles {
+        if !existing.app_ids.iter().any(|a| &a.identifier == id) {
+            info!("Registering App ID {}", id);
+            dev_session
+                .add_app_id(DeveloperDeviceType::Ios, team, name, id)
+                .await
+                .map_err(describe_error)?;
+        }
+    }
+
+    let registered = dev_session
+        .list_app_ids(DeveloperDeviceType::Ios, team)
+        .await
+        .map_err(describe_error)?;
+    let mut app_ids = Vec::new();
+    for (id, _) in &bundles {
+        let app_id = registered
+            .app_ids
+            .iter()
+            .find(|a| &a.identifier == id)
+            .cloned()
+            .ok_or(format!("App ID {} not found in registered App IDs", id))?;
+        app_ids.push(app_id);
+    }
+
+    for app_id in app_ids.iter_mut() {
+        let enabled = app_id
+            .features
+            .get(APP_GROUP_FEATURE)
+            .and_then(|v| v.as_boolean())
+            .unwrap_or(false);
+        if !enabled {
+            debug!("Enabling app groups for {}", app_id.identifier);
+            let mut body = plist::Dictionary::new();
+            body.insert(APP_GROUP_FEATURE.to_string(), plist::Value::Boolean(true));
+            app_id.features = dev_session
+                .update_app_id(DeveloperDeviceType::Ios, team, app_id, &body)
+                .await
+                .map_err(describe_error)?;
+        }
+    }
+    Ok(app_ids)
+}
+
+// Assigns the app group isideload gives every app to all of its App IDs. SideStore, also when
+// bundled with LiveContainer, gets the group and its certificate written into its bundle so it
+// can refresh itself. `bundle_id` is the app's ID before the team ID was appended.
+pub async fn assign_default_group(
+    dev_session: &DeveloperSession,
+    team: &DeveloperTeam,
+    app: &mut Application,
+    bundle_id: &str,
+    app_ids: &[AppId],
+    cert: &CertificateIdentity,
+) -> Result<(), String> {
+    let main_id = app_ids
+        .first()
+        .ok_or("No App IDs registered".to_string())?
+        .identifier
+        .clone();
+    let app_name = app
+        .bundle
+        .bundle_name()
+        .ok_or("No bundle name found in the app".to_string())?
+        .to_string();
+    let is_sidestore = bundle_id == SIDESTORE_BUNDLE_ID;
+    let is_lc_and_sidestore = app
+        .bundle
+        .frameworks()
+        .iter()
+        .any(|f| f.bundle_identifier() == Some(SIDESTORE_BUNDLE_ID));
+
+    let group_identifier = if is_lc_and_sidestore {
+        format!("group.{}.{}", SIDESTORE_BUNDLE_ID, team.team_id)
+    } else {
+        format!("group.{}", main_id)
+    };
+
+    if is_sidestore || is_lc_and_sidestore {
+        app.bundle.app_info.insert(
+            "ALTAppGroups".to_string(),
+            plist::Value::Array(vec![plist::Value::String(group_identifier.clone())]),
+        );
+        let target = if is_lc_and_sidestore {
+            app.bundle
+                .frameworks_mut()
+                .iter_mut()
+                .find(|f| f.bundle_identifier() == Some(SIDESTORE_BUNDLE_ID))
+        } else {
+            Some(&mut app.bundle)
+        };
+        if let Some(target) = target {
+            target.app_info.insert(
+                "ALTCertificateID".to_string(),
+                plist::Value::String(cert.get_serial_number().map_err(describe_error)?),
+            );
+            let p12 = cert.to_pkcs12(&cert.machine_id).map_err(describe_error)?;
+            std::fs::write(target.bundle_dir.join("ALTCertificate.p12"), p12)
+                .map_err(|e| format!("Failed to write ALTCertificate.p12: {}", e))?;
+        }
+    }
+
+    let existing = dev_session
+        .list_application_groups(DeveloperDeviceType::Ios, team)
+        .await
+        .map_err(describe_error)?;
+    let group = match existing
+        .into_iter()
+        .find(|g| g.identifier == group_identifier)
+    {
+        Some(group) => group,
+        None => dev_session
+            .add_application_group(DeveloperDeviceType::Ios, team, &group_identifier, &app_name)
+            .await
+            .map_err(describe_error)?,
+    };
+    for app_id in app_ids {
+        dev_session
+            .assign_application_group_to_app_id(DeveloperDeviceType::Ios, team, app_id, &group)
+            .await
+            .map_err(describe_error)?;
+    }
+    Ok(())
+}
+
+// Downloads the main App ID's profile to `dest`. isideload signs every bundle with it.
+pub async fn download_profile(
+    dev_session: &DeveloperSession,
+    team: &DeveloperTeam,
+    main_app_id: &AppId,
+    dest: &Path,
+) -> Result<(), String> {
+    let profile = dev_session
+        .download_team_provisioning_profile(DeveloperDeviceType::Ios, team, main_app_id)
+        .await
+        .map_err(describe_error)?;
+    let mut file = std::fs::File::create(dest)
+        .map_err(|e| format!("Failed to write provisioning profile: {}", e))?;
+    file.write_all(&profile.encoded_profile)
+        .and_then(|_| file.sync_all())
+        .map_err(|e| format!("Failed to write provisioning profile: {}", e))
+}
+
+// Writes the renamed Info.plists and signs the whole bundle. Without `entitlements`, zsign signs
+// with the ones in the profile.
+pub async fn sign(
+    app: &Application,
+    cert: &CertificateIdentity,
+    profile: &Path,
+    entitlements: Option<PathBuf>,
+) -> Result<(), String> {
+    app.bundle.write_info().map_err(describe_error)?;
+    for bundle in app
+        .bundle
+        .app_extensions()
+        .iter()
+        .chain(app.bundle.frameworks())
+    {
+        bundle.write_info().map_err(describe_error)?;
+    }
+
+    let path = |p: &Path| p.to_string_lossy().to_string();
+    let mut options = ZSignOptions::new(path(&app.bundle.bundle_dir))
+        .with_cert_file(path(cert.get_certificate_file_path()))
+        .with_pkey_file(path(cert.get_private_key_file_path()))
+        .with_prov_file(path(profile))
+        .with_force()
+        .with_disable_cache();
+    if let Some(entitlements) = entitlements {
+        options = options.with_entitlements_file(path(&entitlements));
+    }
+    tauri::async_runtime::spawn_blocking(move || options.sign())
+        .await
+        .map_err(|e| format!("Signing task failed: {}", e))?
+        .map_err(|e| describe_error(Error::ZSignError(e)))
+}
+
+// Whether a profile granting `allowed` permits signing with `requested`. Strings ending in "*"
+// are wildcards, arrays need every requested item to be allowed and false is always allowed.
+fn entitlement_allowed(allowed: &plist::Value, requested: &plist::Value) -> bool {
+    match (allowed, requested) {
+        (_, plist::Value::Boolean(false)) => true,
+        (plist::Value::String(allowed), plist::Value::String(requested)) => {
+            match allowed.strip_suffix('*') {
+                Some(prefix) => requested.starts_with(prefix),
+                None => allowed == requested,
+            }
+        }
+        (plist::Value::Array(allowed), plist::Value::Array(requested)) => requested
+            .iter()
+            .all(|r| allowed.iter().any(|a| entitlement_allowed(a, r))),
+        (plist::Value::Array(allowed), requested) => {
+            allowed.iter().any(|a| entitlement_allowed(a, requested))
+        }
+        (allowed, requested) => allowed == requested,
+    }
+}
+
+// Fails with the entitlements in `requested` the profile doesn't grant, which iOS would refuse
+// to install the app with
+pub fn check_entitlements(
+    profile: &plist::Dictionary,
+    requested: &plist::Dictionary,
+) -> Result<(), String> {
+    let denied: Vec<&str> = requested
+        .iter()
+        .filter(|(key, value)| {
+            !profile
+                .get(key)
+                .is_some_and(|allowed| entitlement_allowed(allowed, value))
+        })
+        .map(|(key, _)| key.as_str())
+        .collect();
+    if denied.is_empty() {
+        return Ok(());
+    }
+    Err(format!(
+        "The provisioning profile doesn't allow these entitlements: {}. Remove them from the \
+         entitlement overrides or the app's preset.",
+        denied.join(", ")
+    ))
+}
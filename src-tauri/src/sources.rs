@@ -0,0 +1,416 @@
+use std::{cmp::Ordering, sync::Mutex};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tauri::{AppHandle, Manager, State, Window};
+use tauri_plugin_store::StoreExt;
+
+use crate::{
+    device::DeviceInfoMutex,
+    download::is_url,
+    inventory::{load_inventory, now},
+    operation::Operation,
+    sideload::{download_and_sideload, SideloadParams, SideloadReport},
+};
+
+// Source JSON as published for AltStore and SideStore. Only the fields iloader uses are read.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawSource {
+    name: Option<String>,
+    identifier: Option<String>,
+    #[serde(default)]
+    apps: Vec<RawApp>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawApp {
+    name: String,
+    bundle_identifier: String,
+    developer_name: Option<String>,
+    subtitle: Option<String>,
+    localized_description: Option<String>,
+    #[serde(rename = "iconURL")]
+    icon_url: Option<String>,
+    // Older sources describe a single version on the app itself
+    version: Option<String>,
+    version_date: Option<String>,
+    #[serde(rename = "downloadURL")]
+    download_url: Option<String>,
+    size: Option<u64>,
+    #[serde(default)]
+    versions: Vec<SourceVersion>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceVersion {
+    pub version: String,
+    pub build_version: Option<String>,
+    pub date: Option<String>,
+    #[serde(alias = "downloadURL")]
+    pub download_url: String,
+    pub size: Option<u64>,
+    #[serde(alias = "minOSVersion")]
+    pub min_os_version: Option<String>,
+    pub localized_description: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceApp {
+    pub name: String,
+    pub bundle_identifier: String,
+    pub developer_name: Option<String>,
+    pub subtitle: Option<String>,
+    pub description: Option<String>,
+    pub icon_url: Option<String>,
+    // Newest first
+    pub versions: Vec<SourceVersion>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Source {
+    pub url: String,
+    pub name: String,
+    pub identifier: Option<String>,
+    pub apps: Vec<SourceApp>,
+    pub last_refreshed: u64,
+    // Set when the last refresh failed, the apps are from the refresh before it
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceAppListing {
+    pub source_url: String,
+    pub source_name: String,
+    pub app: SourceApp,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceUpdate {
+    pub udid: String,
+    pub device_name: String,
+    pub bundle_id: String,
+    pub name: String,
+    pub installed_version: Option<String>,
+    pub available_version: String,
+    pub source_url: String,
+    pub download_url: String,
+}
+
+// Held while the sources are read and written back. Fetching happens outside of it, so a source
+// added or removed during a refresh isn't undone when the refresh saves.
+static SOURCES_LOCK: Mutex<()> = Mutex::new(());
+
+fn load_sources(handle: &AppHandle) -> Result<Vec<Source>, String> {
+    let store = handle
+        .store("sources.json")
+        .map_err(|e| format!("Failed to get store: {:?}", e))?;
+    Ok(store
+        .get("sources")
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default())
+}
+
+fn save_sources(handle: &AppHandle, sources: &[Source]) -> Result<(), String> {
+    let store = handle
+        .store("sources.json")
+        .map_err(|e| format!("Failed to get store: {:?}", e))?;
+    store.set(
+        "sources",
+        serde_json::to_value(sources).map_err(|e| e.to_string())?,
+    );
+    Ok(())
+}
+
+fn update_sources(
+    handle: &AppHandle,
+    f: impl FnOnce(&mut Vec<Source>),
+) -> Result<Vec<Source>, String> {
+    let _lock = SOURCES_LOCK.lock().unwrap();
+    let mut sources = load_sources(handle)?;
+    f(&mut sources);
+    save_sources(handle, &sources)?;
+    Ok(sources)
+}
+
+// Compares dotted version strings numerically, falling back to a string comparison for parts
+// that aren't numbers
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let mut a_parts = a.split(['.', '-']);
+    let mut b_parts = b.split(['.', '-']);
+    loop {
+        match (a_parts.next(), b_parts.next()) {
+            (None, None) => return Ordering::Equal,
+            (a, b) => {
+                let a = a.unwrap_or("0");
+                let b = b.unwrap_or("0");
+                let ord = match (a.parse::<u64>(), b.parse::<u64>()) {
+                    (Ok(a), Ok(b)) => a.cmp(&b),
+                    _ => a.cmp(b),
+                };
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+        }
+    }
+}
+
+fn parse_app(raw: RawApp) -> SourceApp {
+    let mut versions = raw.versions;
+    if versions.is_empty() {
+        if let (Some(version), Some(download_url)) = (raw.version, raw.download_url) {
+            versions.push(SourceVersion {
+                version,
+                build_version: None,
+                date: raw.version_date,
+                download_url,
+                size: raw.size,
+                min_os_version: None,
+                localized_description: None,
+            });
+        }
+    }
+    versions.sort_by(|a, b| compare_versions(&b.version, &a.version));
+
+    SourceApp {
+        name: raw.name,
+        bundle_identifier: raw.bundle_identifier,
+        developer_name: raw.developer_name,
+        subtitle: raw.subtitle,
+        description: raw.localized_description,
+        icon_url: raw.icon_url,
+        versions,
+    }
+}
+
+async fn fetch_source(url: &str) -> Result<Source, String> {
+    info!("Fetching source {}", url);
+    let response = reqwest::get(url)
+        .await
+        .map_err(|e| format!("Failed to fetch source: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "Failed to fetch source: HTTP {}",
+            response.status()
+        ));
+    }
+    let text = response
+        .text()
+        .await
+        .map_err(|e| format!("Failed to read source: {}", e))?;
+    let raw: RawSource =
+        serde_json::from_str(&text).map_err(|e| format!("Failed to parse source: {}", e))?;
+
+    Ok(Source {
+        url: url.to_string(),
+        name: raw.name.unwrap_or_else(|| url.to_string()),
+        identifier: raw.identifier,
+        apps: raw.apps.into_iter().map(parse_app).collect(),
+        last_refreshed: now(),
+        error: None,
+    })
+}
+
+fn find_app<'a>(
+    sources: &'a [Source],
+    source_url: &str,
+    bundle_id: &str,
+) -> Result<&'a SourceApp, String> {
+    sources
+        .iter()
+        .find(|s| s.url == source_url)
+        .ok_or(format!("Source {} is not added", source_url))?
+        .apps
+        .iter()
+        .find(|a| a.bundle_identifier == bundle_id)
+        .ok_or(format!("The source does not contain {}", bundle_id))
+}
+
+#[tauri::command]
+pub fn list_sources(handle: AppHandle) -> Result<Vec<Source>, String> {
+    load_sources(&handle)
+}
+
+#[tauri::command]
+pub async fn add_source(handle: AppHandle, url: String) -> Result<Source, String> {
+    let url = url.trim().to_string();
+    if !is_url(&url) {
+        return Err("Sources must be HTTP(S) URLs".to_string());
+    }
+    let source = fetch_source(&url).await?;
+    update_sources(&handle, |sources| {
+        sources.retain(|s| s.url != url);
+        sources.push(source.clone());
+    })?;
+    Ok(source)
+}
+
+#[tauri::command]
+pub fn remove_source(handle: AppHandle, url: String) -> Result<(), String> {
+    update_sources(&handle, |sources| sources.retain(|s| s.url != url))?;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn refresh_sources(handle: AppHandle) -> Result<Vec<Source>, String> {
+    let mut fetched = vec![];
+    for source in load_sources(&handle)? {
+        let res = fetch_source(&source.url).await;
+        if let Err(e) = &res {
+            warn!("Failed to refresh source {}: {}", source.url, e);
+        }
+        fetched.push((source.url, res));
+    }
+    // Merged into the sources as they are now, by URL
+    update_sources(&handle, |sources| {
+        for source in sources.iter_mut() {
+            match fetched.iter().find(|(url, _)| *url == source.url) {
+                Some((_, Ok(fresh))) => *source = fresh.clone(),
+                Some((_, Err(e))) => source.error = Some(e.clone()),
+                None => {}
+            }
+        }
+    })
+}
+
+#[tauri::command]
+pub fn browse_source_apps(
+    handle: AppHandle,
+    source_url: Option<String>,
+) -> Result<Vec<SourceAppListing>, String> {
+    Ok(load_sources(&handle)?
+        .into_iter()
+        .filter(|s| source_url.as_ref().is_none_or(|url| url == &s.url))
+        .flat_map(|s| {
+            s.apps.into_iter().map(move |app| SourceAppListing {
+                source_url: s.url.clone(),
+                source_name: s.name.clone(),
+                app,
+            })
+        })
+        .collect())
+}
+
+#[tauri::command]
+pub fn search_source_apps(
+    handle: AppHandle,
+    query: String,
+) -> Result<Vec<SourceAppListing>, String> {
+    let query = query.trim().to_lowercase();
+    let mut listings = browse_source_apps(handle, None)?;
+    listings.retain(|l| {
+        [
+            Some(&l.app.name),
+            Some(&l.app.bundle_identifier),
+            l.app.developer_name.as_ref(),
+            l.app.subtitle.as_ref(),
+        ]
+        .into_iter()
+        .flatten()
+        .any(|field| field.to_lowercase().contains(&query))
+    });
+    Ok(listings)
+}
+
+// Inventory apps that a subscribed source has a newer version of, matched by the bundle ID
+// they had before iloader added the team suffix
+#[tauri::command]
+pub fn source_updates(handle: AppHandle) -> Result<Vec<SourceUpdate>, String> {
+    let sources = load_sources(&handle)?;
+    let mut updates = vec![];
+    for entry in load_inventory(&handle)? {
+        for source in &sources {
+            let Some(latest) = source
+                .apps
+                .iter()
+                .find(|a| a.bundle_identifier == entry.original_bundle_id)
+                .and_then(|a| a.versions.first())
+            else {
+                continue;
+            };
+            let newer = match &entry.version {
+                Some(installed) => compare_versions(&latest.version, installed).is_gt(),
+                None => true,
+            };
+            if newer {
+                updates.push(SourceUpdate {
+                    udid: entry.udid.clone(),
+                    device_name: entry.device_name.clone(),
+                    bundle_id: entry.bundle_id.clone(),
+                    name: entry.name.clone(),
+                    installed_version: entry.version.clone(),
+                    available_version: latest.version.clone(),
+                    source_url: source.url.clone(),
+                    download_url: latest.download_url.clone(),
+                });
+                break;
+            }
+        }
+    }
+    Ok(updates)
+}
+
+#[tauri::command]
+pub async fn install_from_source_operation(
+    window: Window,
+    run_id: Option<u64>,
+    device_state: State<'_, DeviceInfoMutex>,
+    source_url: String,
+    bundle_id: String,
+    version: Option<String>,
+    options: Option<SideloadParams>,
+) -> Result<SideloadReport, String> {
+    let op = Operation::new("install_from_source".to_string(), &window, run_id);
+    let handle = window.app_handle().clone();
+    op.set_params(json!({
+        "sourceUrl": source_url,
+        "bundleId": bundle_id,
+        "version": version,
+        "options": options,
+    }));
+    op.start("download")?;
+    let device = {
+        let device_guard = device_state.lock().unwrap();
+        match &*device_guard {
+            Some(d) => d.clone(),
+            None => return op.fail("download", "No device selected".to_string()),
+        }
+    };
+    op.wait_for_account("download", Some(&device.uuid)).await?;
+    let sources = op.fail_if_err("download", load_sources(&handle))?;
+    let app = op.fail_if_err("download", find_app(&sources, &source_url, &bundle_id))?;
+    let release = match &version {
+        Some(version) => app.versions.iter().find(|v| &v.version == version),
+        None => app.versions.first(),
+    };
+    let Some(release) = release else {
+        return op.fail(
+            "download",
+            format!("No matching version of {} in the source", app.name),
+        );
+    };
+    info!(
+        "Installing {} {} from {}",
+        app.name, release.version, source_url
+    );
+
+    let report = download_and_sideload(
+        &op,
+        handle,
+        &window,
+        device,
+        release.download_url.clone(),
+        options.unwrap_or_default(),
+    )
+    .await?;
+    op.complete("install")?;
+    Ok(report)
+}
@@ -23,7 +23,7 @@ import logo from "./iloader.svg";
 import { AppIds } from "./pages/AppIds";
 import { Certificates } from "./pages/Certificates";
 import { Cleanup } from "./pages/Cleanup";
-import { Pairing } from "./pages/Pairing";
+import { Pairing, PairingAppInfo } from "./pages/Pairing";
 import { Settings } from "./pages/Settings";
 import { checkForUpdates } from "./update";
 
@@ -36,6 +36,10 @@ function App() {
   const [openModal, setOpenModal] = useState<
     null | "certificates" | "appids" | "pairing" | "cleanup"
   >(null);
+  // SideStore installs to pick from when the pairing file wasn't placed
+  const [pairingCandidates, setPairingCandidates] = useState<
+    PairingAppInfo[] | null
+  >(null);
   const [version, setVersion] = useState<string>("");
   const [platform, setPlatform] = useState<"mac" | "windows" | "linux">(
     "windows"
@@ -76,20 +80,25 @@ function App() {
   );
 
   const startOperation = useCallback(
-    async (
+    async <T = void,>(
       operation: Operation,
       params: { [key: string]: any }
-    ): Promise<void> => {
+    ): Promise<T> => {
+      const runId = await invoke<number>("reserve_run_id");
       setOperationState({
         current: operation,
+        runId,
         started: [],
         failed: [],
         completed: [],
+        progress: {},
       });
-      return new Promise<void>(async (resolve, reject) => {
+      return new Promise<T>(async (resolve, reject) => {
         const unlistenFn = await listen<OperationUpdate>(
           "operation_" + operation.id,
           (event) => {
+            // Another run of the same operation, e.g. a background refresh
+            if (event.payload.runId !== runId) return;
             setOperationState((old) => {
               if (old == null) return null;
               if (event.payload.updateType === "started") {
@@ -102,6 +111,19 @@ function App() {
                   ...old,
                   completed: [...old.completed, event.payload.stepId],
                 };
+              } else if (event.payload.updateType === "progress") {
+                return {
+                  ...old,
+                  progress: {
+                    ...old.progress,
+                    [event.payload.stepId]: event.payload.progress,
+                  },
+                };
+              } else if (event.payload.updateType === "cancelled") {
+                return {
+                  ...old,
+                  cancelled: event.payload.stepId,
+                };
               } else if (event.payload.updateType === "failed") {
                 return {
                   ...old,
@@ -119,9 +141,12 @@ function App() {
           }
         );
         try {
-          await invoke(operation.id + "_operation", params);
+          const result = await invoke<T>(operation.id + "_operation", {
+            ...params,
+            runId,
+          });
           unlistenFn();
-          resolve();
+          resolve(result);
         } catch (e) {
           unlistenFn();
           reject(e);
@@ -131,6 +156,19 @@ function App() {
     [setOperationState]
   );
 
+  const installSideStore = useCallback(
+    async (operation: Operation, params: { [key: string]: any }) => {
+      const candidates = await startOperation<PairingAppInfo[]>(
+        operation,
+        params
+      );
+      if (candidates.length === 0) return;
+      setPairingCandidates(candidates);
+      setOpenModal("pairing");
+    },
+    [startOperation]
+  );
+
   const ensuredLoggedIn = useCallback((): boolean => {
     if (loggedInAs) return true;
     toast.error("You must be logged in!");
@@ -302,7 +340,7 @@ function App() {
                 <button
                   onClick={() => {
                     if (!ensuredLoggedIn() || !ensureSelectedDevice()) return;
-                    startOperation(installSideStoreOperation, {
+                    installSideStore(installSideStoreOperation, {
                       nightly: false,
                       liveContainer: false,
                     });
@@ -313,7 +351,7 @@ function App() {
                 <button
                   onClick={() => {
                     if (!ensuredLoggedIn() || !ensureSelectedDevice()) return;
-                    startOperation(installSideStoreOperation, {
+                    installSideStore(installSideStoreOperation, {
                       nightly: true,
                       liveContainer: false,
                     });
@@ -324,7 +362,7 @@ function App() {
                 <button
                   onClick={() => {
                     if (!ensuredLoggedIn() || !ensureSelectedDevice()) return;
-                    startOperation(installLiveContainerOperation, {
+                    installSideStore(installLiveContainerOperation, {
                       nightly: false,
                       liveContainer: true,
                     });
@@ -335,7 +373,7 @@ function App() {
                 <button
                   onClick={() => {
                     if (!ensuredLoggedIn() || !ensureSelectedDevice()) return;
-                    startOperation(installLiveContainerOperation, {
+                    installSideStore(installLiveContainerOperation, {
                       nightly: true,
                       liveContainer: true,
                     });
@@ -389,8 +427,14 @@ function App() {
       <Modal isOpen={openModal === "appids"} close={() => setOpenModal(null)}>
         <AppIds />
       </Modal>
-      <Modal isOpen={openModal === "pairing"} close={() => setOpenModal(null)}>
-        <Pairing />
+      <Modal
+        isOpen={openModal === "pairing"}
+        close={() => {
+          setOpenModal(null);
+          setPairingCandidates(null);
+        }}
+      >
+        <Pairing candidates={pairingCandidates ?? undefined} />
       </Modal>
       <Modal isOpen={openModal === "cleanup"} close={() => setOpenModal(null)}>
         <Cleanup />
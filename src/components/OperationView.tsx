@@ -1,3 +1,4 @@
+import { invoke } from "@tauri-apps/api/core";
 import { OperationState } from "./operations";
 import "./OperationView.css";
 import { Modal } from "./Modal";
@@ -15,8 +16,10 @@ export default ({
   closeMenu: () => void;
 }) => {
   const operation = operationState.current;
-  const opFailed = operationState.failed.length > 0;
+  const opCancelled = operationState.cancelled !== undefined;
+  const opFailed = operationState.failed.length > 0 || opCancelled;
   const done =
+    opCancelled ||
     (opFailed &&
       operationState.started.length ==
         operationState.completed.length + operationState.failed.length) ||
@@ -39,7 +42,9 @@ export default ({
         </h3>
         <p>
           {done
-            ? opFailed
+            ? opCancelled
+              ? "Operation cancelled"
+              : opFailed
               ? "Operation failed"
               : "Operation completed"
             : "Please wait..."}
@@ -50,7 +55,10 @@ export default ({
           {operation.steps.map((step) => {
             let failed = operationState.failed.find((f) => f.stepId == step.id);
             let completed = operationState.completed.includes(step.id);
-            let started = operationState.started.includes(step.id);
+            // A cancelled step shows as skipped
+            let started =
+              operationState.started.includes(step.id) &&
+              operationState.cancelled != step.id;
             let notStarted = !failed && !completed && !started;
             return (
               <div className="operation-step" key={step.id}>
@@ -71,7 +79,14 @@ export default ({
                 </div>
 
                 <div className="operation-step-internal">
-                  <p>{step.title}</p>
+                  <p>
+                    {step.title}
+                    {started &&
+                      !completed &&
+                      !failed &&
+                      operationState.progress[step.id] !== undefined &&
+                      ` (${operationState.progress[step.id]}%)`}
+                  </p>
                   {failed && (
                     <pre className="operation-extra-details">
                       {failed.extraDetails}
@@ -88,6 +103,15 @@ export default ({
       )}
       {done && !(!opFailed && operation.successMessage) && <p></p>}
       {done && <button onClick={closeMenu}>Dismiss</button>}
+      {!done && (
+        <button
+          onClick={() =>
+            invoke("cancel_operation", { runId: operationState.runId })
+          }
+        >
+          Cancel
+        </button>
+      )}
     </Modal>
   );
 };
@@ -13,26 +13,87 @@ export type OperationStep = {
 
 export type OperationState = {
   current: Operation;
+  // Reserved before the operation starts, to filter its events and cancel it
+  runId: number;
   completed: string[];
   started: string[];
   failed: {
     stepId: string;
     extraDetails: string;
   }[];
+  cancelled?: string;
+  // Percentages of the steps that report them
+  progress: { [stepId: string]: number };
 };
 
 type OperationInfoUpdate = {
-  updateType: "started" | "finished";
+  runId: number;
+  updateType: "started" | "finished" | "cancelled";
   stepId: string;
 };
 
+type OperationProgressUpdate = {
+  runId: number;
+  updateType: "progress";
+  stepId: string;
+  progress: number;
+};
+
 type OperationFailedUpdate = {
+  runId: number;
   updateType: "failed";
   stepId: string;
   extraDetails: string;
 };
 
-export type OperationUpdate = OperationInfoUpdate | OperationFailedUpdate;
+export type OperationUpdate =
+  | OperationInfoUpdate
+  | OperationProgressUpdate
+  | OperationFailedUpdate;
+
+// The steps sideloading goes through after the app is downloaded
+const signingSteps: OperationStep[] = [
+  {
+    id: "login",
+    title: "Log In",
+  },
+  {
+    id: "team",
+    title: "Find Developer Team",
+  },
+  {
+    id: "prepare",
+    title: "Prepare App",
+  },
+  {
+    id: "certificate",
+    title: "Get Signing Certificate",
+  },
+  {
+    id: "app_ids",
+    title: "Register App IDs",
+  },
+  {
+    id: "profile",
+    title: "Download Provisioning Profile",
+  },
+  {
+    id: "sign",
+    title: "Sign App",
+  },
+];
+
+const sideloadSteps: OperationStep[] = [
+  ...signingSteps,
+  {
+    id: "upload",
+    title: "Upload App",
+  },
+  {
+    id: "install",
+    title: "Install App",
+  },
+];
 
 export const installSideStoreOperation: Operation = {
   id: "install_sidestore",
@@ -44,10 +105,7 @@ export const installSideStoreOperation: Operation = {
       id: "download",
       title: "Download SideStore",
     },
-    {
-      id: "install",
-      title: "Sign & Install SideStore",
-    },
+    ...sideloadSteps,
     {
       id: "pairing",
       title: "Place Pairing File",
@@ -66,10 +124,7 @@ export const installLiveContainerOperation: Operation = {
       id: "download",
       title: "Download SideStore+LiveContainer",
     },
-    {
-      id: "install",
-      title: "Sign & Install SideStore+LiveContainer",
-    },
+    ...sideloadSteps,
     {
       id: "pairing",
       title: "Place Pairing File",
@@ -81,9 +136,124 @@ export const sideloadOperation = {
   id: "sideload",
   title: "Installing App",
   steps: [
+    {
+      id: "download",
+      title: "Download App",
+    },
+    ...sideloadSteps,
+  ],
+};
+
+export const backupAppDataOperation: Operation = {
+  id: "backup_app_data",
+  title: "Backing Up App Data",
+  successTitle: "Backup Complete",
+  steps: [
+    {
+      id: "backup",
+      title: "Archive App Container",
+    },
+  ],
+};
+
+export const restoreAppDataOperation: Operation = {
+  id: "restore_app_data",
+  title: "Restoring App Data",
+  successTitle: "Restore Complete",
+  steps: [
+    {
+      id: "restore",
+      title: "Restore App Container",
+    },
+  ],
+};
+
+export const refreshOperation: Operation = {
+  id: "refresh",
+  title: "Refreshing App",
+  successTitle: "Refresh Complete",
+  steps: [
+    {
+      id: "download",
+      title: "Get App",
+    },
+    ...sideloadSteps,
+  ],
+};
+
+export const signIpaOperation: Operation = {
+  id: "sign_ipa",
+  title: "Signing App",
+  successTitle: "Signed IPA Saved",
+  steps: [
+    ...signingSteps,
+    {
+      id: "package",
+      title: "Export IPA",
+    },
+  ],
+};
+
+export const installIpaOperation: Operation = {
+  id: "install_ipa",
+  title: "Installing Signed App",
+  successTitle: "App Installed",
+  steps: [
+    {
+      id: "upload",
+      title: "Upload Package",
+    },
+    {
+      id: "install",
+      title: "Install Package",
+    },
+  ],
+};
+
+export const uninstallAppOperation: Operation = {
+  id: "uninstall_app",
+  title: "Uninstalling App",
+  successTitle: "App Uninstalled",
+  steps: [
+    {
+      id: "uninstall",
+      title: "Remove App from Device",
+    },
+  ],
+};
+
+export const installFromSourceOperation: Operation = {
+  id: "install_from_source",
+  title: "Installing App",
+  steps: [
+    {
+      id: "download",
+      title: "Download App",
+    },
+    ...sideloadSteps,
+  ],
+};
+
+export const signWithProfileOperation: Operation = {
+  id: "sign_with_profile",
+  title: "Installing App",
+  successTitle: "App Installed",
+  steps: [
+    {
+      id: "prepare",
+      title: "Prepare App",
+    },
+    {
+      id: "sign",
+      title: "Sign with Certificate & Profiles",
+    },
+    {
+      id: "upload",
+      title: "Upload App",
+    },
     {
       id: "install",
-      title: "Sign & Install App",
+      title: "Install App",
     },
   ],
 };
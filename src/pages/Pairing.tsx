@@ -3,14 +3,19 @@ import { invoke } from "@tauri-apps/api/core";
 import { useCallback, useEffect, useRef, useState } from "react";
 import { toast } from "sonner";
 
-type PairingAppInfo = {
+export type PairingAppInfo = {
   name: string;
   bundleId: string;
   path: string;
 };
 
-export const Pairing = () => {
-  const [apps, setApps] = useState<PairingAppInfo[]>([]);
+type PairingProps = {
+  // Installs to choose from, shown instead of every supported app
+  candidates?: PairingAppInfo[];
+};
+
+export const Pairing = ({ candidates }: PairingProps) => {
+  const [apps, setApps] = useState<PairingAppInfo[]>(candidates ?? []);
 
   const [loading, setLoading] = useState<boolean>(false);
   const loadingRef = useRef<boolean>(false);
@@ -48,12 +53,22 @@ export const Pairing = () => {
   );
 
   useEffect(() => {
-    loadApps();
-  }, []);
+    if (candidates) {
+      setApps(candidates);
+    } else {
+      loadApps();
+    }
+  }, [candidates]);
 
   return (
     <>
       <h2>Manage Pairing File</h2>
+      {candidates && (
+        <p>
+          SideStore couldn't be found under the bundle ID it was just installed
+          as. Choose which install receives the pairing file.
+        </p>
+      )}
       {apps.length === 0 ? (
         <div>{loading ? "Loading App..." : "No Supported Apps found."}</div>
       ) : (
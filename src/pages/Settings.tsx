@@ -1,37 +1,70 @@
 import "./Settings.css";
 import { useStore } from "../StoreContext";
 import { useEffect, useRef, useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { toast } from "sonner";
 
 type SettingsProps = {
   showHeading?: boolean;
 };
 
-let anisetteServers = [
-  ["ani.sidestore.io", "SideStore (.io)"],
-  ["ani.sidestore.app", "SideStore (.app)"],
-  ["ani.sidestore.zip", "SideStore (.zip)"],
-  ["ani.846969.xyz", "SideStore (.xyz)"],
-  ["anisette.seasi.dev", "SeasiDev"],
-  ["ani.xu30.top", "SteX"],
-];
+type AnisetteServer = {
+  url: string;
+  name: string;
+  builtin: boolean;
+};
+
+// Older settings stored a bare host, which the backend reaches over HTTPS
+const normalizeUrl = (server: string) => {
+  const trimmed = server.trim().replace(/\/+$/, "");
+  return /^https?:\/\//.test(trimmed) ? trimmed : `https://${trimmed}`;
+};
+
 export const Settings = ({ showHeading = true }: SettingsProps) => {
   const [anisetteServer, setAnisetteServer] = useStore<string>(
     "anisetteServer",
     "ani.sidestore.io"
   );
-  const [isCustom, setIsCustom] = useState<boolean>(
-    anisetteServers.every(([value, _]) => value !== anisetteServer)
+  // The built-in servers and the ones the user added, as the backend tries them
+  const [anisetteServers, setAnisetteServers] = useState<AnisetteServer[]>(
+    []
   );
+  const [isCustom, setIsCustom] = useState<boolean>(false);
   const [dropdownOpen, setDropdownOpen] = useState<boolean>(false);
   const dropdownRef = useRef<HTMLDivElement | null>(null);
   const customInputRef = useRef<HTMLInputElement | null>(null);
-  const lastCustomValueRef = useRef<string>(
-    anisetteServers.every(([value]) => value !== anisetteServer)
-      ? anisetteServer
-      : "ani.yourserver.com"
-  );
+  const lastCustomValueRef = useRef<string>("ani.yourserver.com");
   const anisetteLabelId = "anisette-label";
 
+  useEffect(() => {
+    invoke<AnisetteServer[]>("list_anisette_servers")
+      .then((servers) => {
+        setAnisetteServers(servers);
+        if (!servers.some((s) => s.url === normalizeUrl(anisetteServer))) {
+          lastCustomValueRef.current = anisetteServer;
+          setIsCustom(true);
+        }
+      })
+      .catch((e) => toast.error("Failed to load anisette servers: " + e));
+  }, []);
+
+  const addCustomServer = () => {
+    const promise = invoke<AnisetteServer>("add_anisette_server", {
+      url: anisetteServer,
+    }).then(async (server) => {
+      setAnisetteServers(
+        await invoke<AnisetteServer[]>("list_anisette_servers")
+      );
+      setIsCustom(false);
+      setAnisetteServer(server.url);
+    });
+    toast.promise(promise, {
+      loading: "Adding server...",
+      success: "Server added!",
+      error: (e) => "Failed to add server: " + e,
+    });
+  };
+
   useEffect(() => {
     if (!dropdownOpen) return;
 
@@ -76,18 +109,23 @@ export const Settings = ({ showHeading = true }: SettingsProps) => {
   const deactivateCustom = () => {
     setDropdownOpen(false);
     setIsCustom(false);
-    setAnisetteServer(anisetteServers[0][0]);
+    if (anisetteServers.length > 0) {
+      setAnisetteServer(anisetteServers[0].url);
+    }
   };
 
   const customLabel =
     lastCustomValueRef.current && lastCustomValueRef.current.length > 0
       ? `Custom (${lastCustomValueRef.current})`
       : "Custom";
-  const dropdownOptions = [...anisetteServers, ["custom", customLabel]];
-  const selectedValue = isCustom ? "custom" : anisetteServer;
+  const dropdownOptions = [
+    ...anisetteServers.map((s) => [s.url, s.name]),
+    ["custom", customLabel],
+  ];
+  const selectedValue = isCustom ? "custom" : normalizeUrl(anisetteServer);
   const presetLabel =
-    anisetteServers.find(([value]) => value === anisetteServer)?.[1] ??
-    "Select Server";
+    anisetteServers.find((s) => s.url === normalizeUrl(anisetteServer))
+      ?.name ?? "Select Server";
   const selectedLabel = isCustom
     ? lastCustomValueRef.current || "Custom Anisette Server"
     : presetLabel;
@@ -198,6 +236,18 @@ export const Settings = ({ showHeading = true }: SettingsProps) => {
               ref={customInputRef}
             />
           )}
+          {isCustom && (
+            <div className="custom-toggle">
+              <button
+                type="button"
+                className="link-button"
+                disabled={anisetteServer.trim().length === 0}
+                onClick={addCustomServer}
+              >
+                Save to server list
+              </button>
+            </div>
+          )}
         </div>
         <div>
           <label className="settings-label">